@@ -6,13 +6,19 @@ use crate::{HEX_RADIUS, UNIT_RADIUS};
 pub struct GameAssets {
     pub hexagone: Handle<Mesh>,
     pub circle: Handle<Mesh>,
+    pub path_dot: Handle<Mesh>,
     pub white: Handle<ColorMaterial>,
     pub black: Handle<ColorMaterial>,
     pub gray: Handle<ColorMaterial>,
     pub red: Handle<ColorMaterial>,
     pub blue: Handle<ColorMaterial>,
+    pub forest_color: Handle<ColorMaterial>,
+    pub swamp_color: Handle<ColorMaterial>,
+    pub water_color: Handle<ColorMaterial>,
+    pub road_color: Handle<ColorMaterial>,
     pub movement_hex_color: Handle<ColorMaterial>,
     pub cursor_color: Handle<ColorMaterial>,
+    pub path_preview_color: Handle<ColorMaterial>,
     pub font: Handle<Font>,
 }
 
@@ -34,13 +40,19 @@ fn load_assets(
     *scene_assets = GameAssets {
         hexagone: meshes.add(RegularPolygon::new(HEX_RADIUS, 6)),
         circle: meshes.add(Circle::new(UNIT_RADIUS)),
+        path_dot: meshes.add(Circle::new(UNIT_RADIUS / 4.)),
         white: materials.add(Color::WHITE),
         black: materials.add(Color::BLACK),
         gray: materials.add(Color::GRAY),
         red: materials.add(Color::RED),
         blue: materials.add(Color::BLUE),
+        forest_color: materials.add(Color::rgb(0.13, 0.55, 0.13)),
+        swamp_color: materials.add(Color::rgb(0.42, 0.45, 0.25)),
+        water_color: materials.add(Color::rgb(0.25, 0.5, 0.9)),
+        road_color: materials.add(Color::rgb(0.8, 0.7, 0.5)),
         movement_hex_color: materials.add(Color::rgba(0., 1., 0., 0.3)),
         cursor_color: materials.add(Color::rgba(0., 1., 1., 0.5)),
+        path_preview_color: materials.add(Color::rgb(1., 0.6, 0.)),
         font: asset_server.load("Kenney Pixel.ttf"),
     }
 }
@@ -0,0 +1,260 @@
+use bevy::{
+    input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel},
+    prelude::*,
+    window::PrimaryWindow,
+};
+
+use crate::{
+    hex_pos::HexPos,
+    input::{Action, ActionState},
+    map::Hex,
+    HEX_RADIUS,
+};
+
+#[derive(Component)]
+pub struct MainCamera;
+
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct CameraSettings {
+    /// Logical pixels per second at zoom 1, the speed follows the zoom level.
+    pub pan_speed: f32,
+    /// Scale change per mouse wheel line.
+    pub zoom_step: f32,
+    pub min_scale: f32,
+    pub max_scale: f32,
+    pub edge_scroll: bool,
+    /// Distance from the window border, in logical pixels, that triggers edge scrolling.
+    pub edge_margin: f32,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            pan_speed: 600.,
+            zoom_step: 0.1,
+            min_scale: 0.25,
+            max_scale: 3.,
+            edge_scroll: false,
+            edge_margin: 10.,
+        }
+    }
+}
+
+/// World area covered by the map, the camera centre is kept inside it.
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct CameraBounds {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraSettings>()
+            .init_resource::<CameraBounds>()
+            .add_systems(PostStartup, spawn_camera)
+            .add_systems(
+                Update,
+                (
+                    update_camera_bounds,
+                    pan_camera_keys,
+                    pan_camera_drag,
+                    pan_camera_edge,
+                    zoom_camera,
+                    clamp_camera,
+                )
+                    .chain(),
+            );
+    }
+}
+
+fn spawn_camera(mut commands: Commands) {
+    commands.spawn((Camera2dBundle::default(), MainCamera));
+}
+
+fn update_camera_bounds(
+    mut bounds: ResMut<CameraBounds>,
+    added_hex_query: Query<(), Added<Hex>>,
+    hex_query: Query<&HexPos, With<Hex>>,
+) {
+    if added_hex_query.is_empty() {
+        return;
+    }
+
+    let (min, max) = hex_query.iter().fold(
+        (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+        |(min, max), hex_pos| {
+            let pixel = hex_pos.pos.to_vec3_pixel().truncate();
+            (min.min(pixel), max.max(pixel))
+        },
+    );
+
+    *bounds = CameraBounds {
+        min: min - HEX_RADIUS,
+        max: max + HEX_RADIUS,
+    };
+}
+
+fn pan_camera_keys(
+    actions: Res<ActionState>,
+    time: Res<Time>,
+    settings: Res<CameraSettings>,
+    mut camera_query: Query<(&mut Transform, &OrthographicProjection), With<MainCamera>>,
+) {
+    let Ok((mut transform, projection)) = camera_query.get_single_mut() else {
+        return;
+    };
+
+    let mut direction = Vec2::ZERO;
+    if actions.pressed(Action::PanUp) {
+        direction.y += 1.;
+    }
+    if actions.pressed(Action::PanDown) {
+        direction.y -= 1.;
+    }
+    if actions.pressed(Action::PanLeft) {
+        direction.x -= 1.;
+    }
+    if actions.pressed(Action::PanRight) {
+        direction.x += 1.;
+    }
+
+    let pan = direction.normalize_or_zero()
+        * settings.pan_speed
+        * projection.scale
+        * time.delta_seconds();
+    transform.translation += pan.extend(0.);
+}
+
+fn pan_camera_drag(
+    actions: Res<ActionState>,
+    mut mouse_motion_r: EventReader<MouseMotion>,
+    mut camera_query: Query<(&mut Transform, &OrthographicProjection), With<MainCamera>>,
+) {
+    // read every frame so motion from before the button went down is dropped
+    let delta = mouse_motion_r
+        .read()
+        .fold(Vec2::ZERO, |delta, event| delta + event.delta);
+
+    if !actions.pressed(Action::PanCamera) {
+        return;
+    }
+    let Ok((mut transform, projection)) = camera_query.get_single_mut() else {
+        return;
+    };
+
+    // screen y grows downward, world y upward
+    transform.translation += (delta * Vec2::new(-1., 1.) * projection.scale).extend(0.);
+}
+
+fn pan_camera_edge(
+    actions: Res<ActionState>,
+    time: Res<Time>,
+    settings: Res<CameraSettings>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut camera_query: Query<(&mut Transform, &OrthographicProjection), With<MainCamera>>,
+) {
+    if !settings.edge_scroll || actions.pointer_over_ui() {
+        return;
+    }
+    let Ok(window) = window_query.get_single() else {
+        return;
+    };
+    let Some(cursor) = window.cursor_position() else {
+        return;
+    };
+    let Ok((mut transform, projection)) = camera_query.get_single_mut() else {
+        return;
+    };
+
+    let mut direction = Vec2::ZERO;
+    if cursor.x < settings.edge_margin {
+        direction.x -= 1.;
+    }
+    if cursor.x > window.width() - settings.edge_margin {
+        direction.x += 1.;
+    }
+    if cursor.y < settings.edge_margin {
+        direction.y += 1.;
+    }
+    if cursor.y > window.height() - settings.edge_margin {
+        direction.y -= 1.;
+    }
+
+    let pan = direction.normalize_or_zero()
+        * settings.pan_speed
+        * projection.scale
+        * time.delta_seconds();
+    transform.translation += pan.extend(0.);
+}
+
+/// Zooms around the cursor, the world point under it stays put.
+fn zoom_camera(
+    actions: Res<ActionState>,
+    mut mouse_wheel_r: EventReader<MouseWheel>,
+    settings: Res<CameraSettings>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut camera_query: Query<(&mut Transform, &mut OrthographicProjection), With<MainCamera>>,
+) {
+    let mut lines = mouse_wheel_r
+        .read()
+        .map(|event| match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / 16.,
+        })
+        .sum::<f32>();
+    // the wheel scrolls the panel under the pointer instead
+    if actions.pointer_over_ui() {
+        lines = 0.;
+    }
+    if actions.just_pressed(Action::ZoomIn) {
+        lines += 1.;
+    }
+    if actions.just_pressed(Action::ZoomOut) {
+        lines -= 1.;
+    }
+
+    if lines == 0. {
+        return;
+    }
+    let Ok((mut transform, mut projection)) = camera_query.get_single_mut() else {
+        return;
+    };
+
+    let old_scale = projection.scale;
+    let new_scale = (old_scale * (1. - settings.zoom_step).powf(lines))
+        .clamp(settings.min_scale, settings.max_scale);
+    projection.scale = new_scale;
+
+    let Some((window, cursor)) = window_query
+        .get_single()
+        .ok()
+        .and_then(|window| window.cursor_position().map(|cursor| (window, cursor)))
+    else {
+        return;
+    };
+
+    let from_center =
+        (cursor - Vec2::new(window.width(), window.height()) / 2.) * Vec2::new(1., -1.);
+    transform.translation += (from_center * (old_scale - new_scale)).extend(0.);
+}
+
+fn clamp_camera(
+    bounds: Res<CameraBounds>,
+    mut camera_query: Query<&mut Transform, With<MainCamera>>,
+) {
+    let Ok(mut transform) = camera_query.get_single_mut() else {
+        return;
+    };
+
+    if bounds.min.cmpgt(bounds.max).any() {
+        return;
+    }
+
+    let clamped = transform
+        .translation
+        .truncate()
+        .clamp(bounds.min, bounds.max);
+    transform.translation = clamped.extend(transform.translation.z);
+}
@@ -0,0 +1,320 @@
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::{
+    cursor::{CursorCancelEvent, CursorGridClickEvent},
+    hex_pos::HexPos,
+    history::{CommandHistory, GameCommand},
+    input::{Action, ActionState},
+    movement::movement_idle,
+    obstacle::Obstacle,
+    replay::{not_replaying, CommandAppliedEvent, ReplayAction},
+    rng::GameRng,
+    schedule::UpdateSet,
+    turn::{Team, TurnEndedEvent, TurnStartedEvent},
+    unit::{CurrentActiveUnit, Unit},
+    Axial,
+};
+
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+#[reflect(Component)]
+pub struct Health {
+    pub max: usize,
+    pub current: usize,
+}
+
+impl Health {
+    pub fn new(max: usize) -> Self {
+        Self { max, current: max }
+    }
+}
+
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+#[reflect(Component)]
+pub struct Attack {
+    pub damage: usize,
+    /// Farthest hex distance that can be hit, anything past 1 needs line of sight.
+    pub range: usize,
+    /// Whether the unit already attacked this turn.
+    pub spent: bool,
+}
+
+impl Attack {
+    pub fn new(damage: usize, range: usize) -> Self {
+        Self {
+            damage,
+            range,
+            spent: false,
+        }
+    }
+}
+
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+#[reflect(Component)]
+pub struct Defense {
+    pub value: usize,
+}
+
+/// What the next grid click is aimed at, on top of selecting and moving units.
+#[derive(Resource, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Targeting {
+    #[default]
+    None,
+    Attack,
+}
+
+/// Asks for `attacker` to hit `target`, sent by grid clicks and replays alike.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct AttackEvent {
+    pub attacker: Entity,
+    pub target: Entity,
+}
+
+#[derive(Event, Debug)]
+pub struct DamageDealtEvent {
+    pub attacker: Entity,
+    pub target: Entity,
+    pub amount: usize,
+}
+
+/// Sent as the unit is despawned, so it carries what readers can no longer query.
+#[derive(Event, Debug)]
+pub struct UnitDiedEvent {
+    pub pos: Axial,
+    pub name: Name,
+    pub team: Team,
+}
+
+pub struct CombatPlugin;
+
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Health>()
+            .register_type::<Attack>()
+            .register_type::<Defense>()
+            .init_resource::<Targeting>()
+            .add_event::<AttackEvent>()
+            .add_event::<DamageDealtEvent>()
+            .add_event::<UnitDiedEvent>()
+            .add_systems(
+                Update,
+                (
+                    (
+                        refresh_attacks,
+                        start_targeting.run_if(movement_idle).run_if(not_replaying),
+                        attack_target_clicked,
+                    )
+                        .chain()
+                        .in_set(UpdateSet::UserInput),
+                    (leave_targeting, resolve_attack, unit_death)
+                        .chain()
+                        .in_set(UpdateSet::Combat),
+                ),
+            );
+    }
+}
+
+fn refresh_attacks(
+    mut turn_started_event_r: EventReader<TurnStartedEvent>,
+    mut unit_query: Query<(Entity, &Team, &mut Attack), With<Unit>>,
+) {
+    for event in turn_started_event_r.read() {
+        for (entity, team, mut attack) in unit_query.iter_mut() {
+            let starts_turn = match event.unit {
+                Some(unit) => unit == entity,
+                None => team.id == event.team,
+            };
+
+            if starts_turn {
+                attack.spent = false;
+            }
+        }
+    }
+}
+
+fn start_targeting(
+    actions: Res<ActionState>,
+    selected_unit: Res<CurrentActiveUnit>,
+    attack_query: Query<&Attack, With<Unit>>,
+    mut targeting: ResMut<Targeting>,
+) {
+    if !actions.just_pressed(Action::Attack) {
+        return;
+    }
+
+    let can_attack = selected_unit
+        .entity
+        .and_then(|entity| attack_query.get(entity).ok())
+        .is_some_and(|attack| !attack.spent);
+
+    if can_attack {
+        *targeting = Targeting::Attack;
+    }
+}
+
+/// Leaves targeting after any click, hit or miss, on cancel and when the turn ends.
+/// Runs once input is over so the click that leaves targeting does not also select or move.
+fn leave_targeting(
+    mut cursor_grid_click_event_r: EventReader<CursorGridClickEvent>,
+    mut cursor_cancel_event_r: EventReader<CursorCancelEvent>,
+    mut turn_ended_event_r: EventReader<TurnEndedEvent>,
+    mut targeting: ResMut<Targeting>,
+) {
+    let clicked = cursor_grid_click_event_r.read().count() > 0;
+    let cancelled = cursor_cancel_event_r.read().count() > 0;
+    let turn_ended = turn_ended_event_r.read().count() > 0;
+
+    if (clicked || cancelled || turn_ended) && *targeting != Targeting::None {
+        *targeting = Targeting::None;
+    }
+}
+
+/// Whether a unit standing on `from` can hit `target`, anything past melee range needs line of sight.
+fn in_attack_range(attack: &Attack, from: Axial, target: Axial, obstacles: &[Axial]) -> bool {
+    let distance = from.distance(&target);
+
+    !attack.spent
+        && distance <= attack.range
+        && (distance <= 1 || from.line_of_sight(&target, obstacles))
+}
+
+fn attack_target_clicked(
+    mut cursor_grid_click_event_r: EventReader<CursorGridClickEvent>,
+    mut attack_event_w: EventWriter<AttackEvent>,
+    targeting: Res<Targeting>,
+    selected_unit: Res<CurrentActiveUnit>,
+    obstacle_query: Query<&HexPos, With<Obstacle>>,
+    unit_query: Query<(Entity, &HexPos, &Team, Option<&Attack>), With<Unit>>,
+) {
+    for event in cursor_grid_click_event_r.read() {
+        if *targeting != Targeting::Attack {
+            continue;
+        }
+        let Some(attacker) = selected_unit.entity else {
+            continue;
+        };
+        let Ok((_, attacker_pos, attacker_team, Some(attack))) = unit_query.get(attacker) else {
+            continue;
+        };
+
+        let Some(target) = unit_query
+            .iter()
+            .find(|(_, pos, team, _)| pos.pos == event.pos && *team != attacker_team)
+            .map(|(entity, _, _, _)| entity)
+        else {
+            continue;
+        };
+
+        let obstacles = obstacle_query
+            .iter()
+            .map(|pos| pos.pos)
+            .collect::<Vec<Axial>>();
+
+        if !in_attack_range(attack, attacker_pos.pos, event.pos, &obstacles) {
+            continue;
+        }
+
+        attack_event_w.send(AttackEvent { attacker, target });
+    }
+}
+
+pub fn resolve_attack(
+    mut attack_event_r: EventReader<AttackEvent>,
+    mut damage_dealt_event_w: EventWriter<DamageDealtEvent>,
+    mut command_applied_event_w: EventWriter<CommandAppliedEvent>,
+    mut history: ResMut<CommandHistory>,
+    mut game_rng: ResMut<GameRng>,
+    obstacle_query: Query<&HexPos, With<Obstacle>>,
+    mut unit_query: Query<
+        (
+            &HexPos,
+            &Team,
+            Option<&mut Attack>,
+            &mut Health,
+            Option<&Defense>,
+        ),
+        With<Unit>,
+    >,
+) {
+    for event in attack_event_r.read() {
+        let (attacker, target) = (event.attacker, event.target);
+        let Ok((attacker_pos, attacker_team, Some(attack), _, _)) = unit_query.get(attacker) else {
+            continue;
+        };
+        let (attacker_pos, attacker_team, attack) = (attacker_pos.pos, *attacker_team, *attack);
+
+        let Ok((target_pos, target_team, _, _, _)) = unit_query.get(target) else {
+            continue;
+        };
+        let target_pos = target_pos.pos;
+        let obstacles = obstacle_query
+            .iter()
+            .map(|pos| pos.pos)
+            .collect::<Vec<Axial>>();
+
+        if *target_team == attacker_team
+            || !in_attack_range(&attack, attacker_pos, target_pos, &obstacles)
+        {
+            continue;
+        }
+
+        let Ok((_, _, _, mut health, defense)) = unit_query.get_mut(target) else {
+            continue;
+        };
+        let roll = game_rng.gen_range(attack.damage.saturating_sub(1)..=attack.damage + 1);
+        let amount = roll
+            .saturating_sub(defense.map_or(0, |defense| defense.value))
+            .max(1);
+        health.current = health.current.saturating_sub(amount);
+
+        if let Ok((_, _, Some(mut attack), _, _)) = unit_query.get_mut(attacker) {
+            attack.spent = true;
+        }
+
+        history.push(GameCommand::Attack);
+        command_applied_event_w.send(CommandAppliedEvent {
+            action: ReplayAction::Attack {
+                attacker: attacker_pos,
+                target: target_pos,
+            },
+        });
+        damage_dealt_event_w.send(DamageDealtEvent {
+            attacker,
+            target,
+            amount,
+        });
+    }
+}
+
+pub fn unit_death(
+    mut damage_dealt_event_r: EventReader<DamageDealtEvent>,
+    mut unit_died_event_w: EventWriter<UnitDiedEvent>,
+    mut commands: Commands,
+    mut selected_unit: ResMut<CurrentActiveUnit>,
+    unit_query: Query<(&HexPos, &Health, &Name, &Team), With<Unit>>,
+) {
+    for event in damage_dealt_event_r.read() {
+        let Ok((pos, health, name, team)) = unit_query.get(event.target) else {
+            continue;
+        };
+
+        if health.current > 0 {
+            continue;
+        }
+
+        // despawning drops the `Obstacle` footprint along with the unit
+        commands.entity(event.target).despawn_recursive();
+        unit_died_event_w.send(UnitDiedEvent {
+            pos: pos.pos,
+            name: name.clone(),
+            team: *team,
+        });
+
+        if selected_unit.entity == Some(event.target) {
+            selected_unit.entity = None;
+        } else {
+            // the survivor may now walk through the freed hex, redraw its range
+            selected_unit.set_changed();
+        }
+    }
+}
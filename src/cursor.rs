@@ -1,30 +1,57 @@
 use bevy::{
     prelude::*,
+    render::camera::NormalizedRenderTarget,
     sprite::{MaterialMesh2dBundle, Mesh2dHandle},
+    window::{PrimaryWindow, WindowResized},
 };
 
-use crate::{asset_loader::GameAssets, Axial, Vec3Extra};
+use crate::{
+    asset_loader::GameAssets,
+    hex_pos::HexPos,
+    input::{Action, ActionState},
+    map::Hex,
+    movement::movement_idle,
+    replay::not_replaying,
+    Axial, HexDirection, Vec3Extra,
+};
 
 #[derive(Event, Debug)]
 pub struct CursorGridClickEvent {
     pub pos: Axial,
 }
 
+#[allow(dead_code)]
 #[derive(Event, Debug)]
 pub struct CursorWorldMoveEvent {
     pub pos: Vec3,
 }
 
+#[allow(dead_code)]
 #[derive(Event, Debug)]
 pub struct CursorGridMoveEvent {
     pub pos: Axial,
 }
 
+/// Asks to back out of the current action, the counterpart of a click.
+#[derive(Event, Debug)]
+pub struct CursorCancelEvent;
+
+/// Hex under the cursor, `None` while the cursor is off the map.
 #[derive(Resource, Default, Debug)]
 pub struct CursorGridPos {
-    pub pos: Axial,
+    pub pos: Option<Axial>,
+}
+
+/// Whether the mouse drives the cursor, keys and gamepads take over until the mouse moves again.
+#[derive(Resource, Default, Debug)]
+struct MouseCursor {
+    active: bool,
 }
 
+/// Delay before a held stick or d-pad moves the cursor again.
+const GAMEPAD_REPEAT: f32 = 0.2;
+const GAMEPAD_DEADZONE: f32 = 0.5;
+
 pub struct CursorPlugin;
 
 #[derive(Component)]
@@ -33,11 +60,27 @@ struct HexCursor;
 impl Plugin for CursorPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<CursorGridPos>()
+            .init_resource::<MouseCursor>()
             .add_event::<CursorGridClickEvent>()
+            .add_event::<CursorCancelEvent>()
             .add_event::<CursorWorldMoveEvent>()
             .add_event::<CursorGridMoveEvent>()
-            .add_systems(PostStartup, spawn_cursor)
-            .add_systems(Update, (cursor_move, cursor_click));
+            .add_systems(
+                PostStartup,
+                spawn_cursor.run_if(resource_exists::<GameAssets>),
+            )
+            .add_systems(
+                Update,
+                (
+                    (cursor_move, cursor_keys, cursor_gamepad),
+                    (
+                        cursor_click.run_if(movement_idle).run_if(not_replaying),
+                        cursor_cancel.run_if(not_replaying),
+                    ),
+                    update_cursor_sprite,
+                )
+                    .chain(),
+            );
     }
 }
 
@@ -50,6 +93,7 @@ fn spawn_cursor(mut commands: Commands, game_assets: Res<GameAssets>) {
                 translation: Vec3::new(0., 0., 1.),
                 ..default()
             },
+            visibility: Visibility::Hidden,
             ..default()
         },
         HexCursor,
@@ -57,73 +101,270 @@ fn spawn_cursor(mut commands: Commands, game_assets: Res<GameAssets>) {
     ));
 }
 
-fn calc_cursor_pos(
-    window: &Window,
-    camera_transform: &Transform,
-    cursor_moved_event: &CursorMoved,
-) -> (Vec3, Axial) {
-    let window_size = Vec2::new(window.width() as f32, window.height() as f32);
-    let cursor_pos_adjusted_window =
-        (cursor_moved_event.position - window_size / 2.) * Vec2::new(1., -1.);
-    let cursor_pos_adjusted_camera =
-        camera_transform.compute_matrix() * cursor_pos_adjusted_window.extend(0.).extend(1.);
-    let cursor_adjusted_to_grid = Axial::from_pixel_vec3(cursor_pos_adjusted_camera.truncate());
+/// World position under `cursor`, seen through the topmost camera rendering that part of `window`.
+fn viewport_to_world(
+    window: Entity,
+    cursor: Vec2,
+    primary_window: Option<Entity>,
+    cameras: &Query<(&Camera, &GlobalTransform)>,
+) -> Option<Vec2> {
+    cameras
+        .iter()
+        .filter(|(camera, _)| camera.is_active)
+        .filter(
+            |(camera, _)| match camera.target.normalize(primary_window) {
+                Some(NormalizedRenderTarget::Window(window_ref)) => window_ref.entity() == window,
+                _ => false,
+            },
+        )
+        .filter_map(|(camera, camera_transform)| {
+            let viewport = camera.logical_viewport_rect()?;
+            viewport
+                .contains(cursor)
+                .then_some((camera, camera_transform, viewport.min))
+        })
+        .max_by_key(|(camera, _, _)| camera.order)
+        .and_then(|(camera, camera_transform, viewport_min)| {
+            camera.viewport_to_world_2d(camera_transform, cursor - viewport_min)
+        })
+}
 
-    (
-        cursor_pos_adjusted_camera.truncate(),
-        cursor_adjusted_to_grid,
-    )
+/// Moves the cursor to `pos`, only announcing hexes that are part of the map.
+fn set_cursor_pos(
+    cursor_grid_pos: &mut CursorGridPos,
+    pos: Option<Axial>,
+    cursor_grid_move_event_w: &mut EventWriter<CursorGridMoveEvent>,
+) {
+    if cursor_grid_pos.pos == pos {
+        return;
+    }
+
+    cursor_grid_pos.pos = pos;
+    if let Some(pos) = pos {
+        cursor_grid_move_event_w.send(CursorGridMoveEvent { pos });
+    }
 }
 
 fn cursor_move(
     mut cursor_grid_move_event_w: EventWriter<CursorGridMoveEvent>,
     mut cursor_world_move_event_w: EventWriter<CursorWorldMoveEvent>,
     mut cursor_grid_pos: ResMut<CursorGridPos>,
+    mut mouse_cursor: ResMut<MouseCursor>,
     mut cursor_moved_events: EventReader<CursorMoved>,
-    mut cursor_query: Query<&mut Transform, (With<HexCursor>, Without<Camera>)>,
-    windows: Query<&Window>,
-    cameras: Query<&Transform, With<Camera>>,
+    mut window_resized_events: EventReader<WindowResized>,
+    windows: Query<(Entity, &Window)>,
+    primary_window: Query<Entity, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    moved_camera_query: Query<
+        (),
+        (
+            With<Camera>,
+            Or<(Changed<GlobalTransform>, Changed<OrthographicProjection>)>,
+        ),
+    >,
+    hex_query: Query<&HexPos, With<Hex>>,
 ) {
-    let Ok(window) = windows.get_single() else {
-        return;
-    };
+    let cursor_moved = cursor_moved_events.read().count() > 0;
+    // panning, zooming or resizing moves the world under a still mouse
+    let view_changed = window_resized_events.read().count() > 0 || !moved_camera_query.is_empty();
 
-    let Ok(camera_transform) = cameras.get_single() else {
+    if cursor_moved {
+        mouse_cursor.active = true;
+    }
+    if !mouse_cursor.active || (!cursor_moved && !view_changed) {
         return;
-    };
+    }
+
+    let primary_window = primary_window.get_single().ok();
+    let world_pos = windows.iter().find_map(|(entity, window)| {
+        let cursor = window.cursor_position()?;
+        viewport_to_world(entity, cursor, primary_window, &cameras)
+    });
 
-    let Ok(mut cursor) = cursor_query.get_single_mut() else {
+    let Some(world_pos) = world_pos else {
+        set_cursor_pos(&mut cursor_grid_pos, None, &mut cursor_grid_move_event_w);
         return;
     };
 
-    for cursor_moved_event in cursor_moved_events.read() {
-        let (cursor_pos_adjusted_camera, cursor_adjusted_to_grid) =
-            calc_cursor_pos(window, camera_transform, cursor_moved_event);
-
+    if cursor_moved {
         cursor_world_move_event_w.send(CursorWorldMoveEvent {
-            pos: cursor_pos_adjusted_camera,
+            pos: world_pos.extend(0.),
         });
+    }
+
+    let hex = Axial::from_pixel_vec3(world_pos.extend(0.));
+    let on_map = hex_query.iter().any(|hex_pos| hex_pos.pos == hex);
+    set_cursor_pos(
+        &mut cursor_grid_pos,
+        on_map.then_some(hex),
+        &mut cursor_grid_move_event_w,
+    );
+}
 
-        if cursor_grid_pos.pos != cursor_adjusted_to_grid {
-            cursor_grid_pos.pos = cursor_adjusted_to_grid;
-            // mouse position event in relation to the grid
-            cursor_grid_move_event_w.send(CursorGridMoveEvent {
-                pos: cursor_adjusted_to_grid,
-            });
+/// Moves the cursor one hex towards `direction`, staying on the map.
+fn step_cursor(
+    cursor_grid_pos: &mut CursorGridPos,
+    mouse_cursor: &mut MouseCursor,
+    direction: HexDirection,
+    hex_query: &Query<&HexPos, With<Hex>>,
+    cursor_grid_move_event_w: &mut EventWriter<CursorGridMoveEvent>,
+) {
+    mouse_cursor.active = false;
 
-            cursor.translation = cursor_adjusted_to_grid.to_vec3_pixel().set_z(10.);
+    let hex = match cursor_grid_pos.pos {
+        Some(pos) => pos.neighbor(direction),
+        None => Axial::ZERO,
+    };
+    if hex_query.iter().any(|hex_pos| hex_pos.pos == hex) {
+        set_cursor_pos(cursor_grid_pos, Some(hex), cursor_grid_move_event_w);
+    }
+}
+
+fn cursor_keys(
+    actions: Res<ActionState>,
+    mut cursor_grid_move_event_w: EventWriter<CursorGridMoveEvent>,
+    mut cursor_grid_pos: ResMut<CursorGridPos>,
+    mut mouse_cursor: ResMut<MouseCursor>,
+    hex_query: Query<&HexPos, With<Hex>>,
+) {
+    let bindings = [
+        (Action::CursorEast, HexDirection::East),
+        (Action::CursorNorthEast, HexDirection::NorthEast),
+        (Action::CursorNorthWest, HexDirection::NorthWest),
+        (Action::CursorWest, HexDirection::West),
+        (Action::CursorSouthWest, HexDirection::SouthWest),
+        (Action::CursorSouthEast, HexDirection::SouthEast),
+    ];
+
+    for (action, direction) in bindings {
+        if actions.just_pressed(action) {
+            step_cursor(
+                &mut cursor_grid_pos,
+                &mut mouse_cursor,
+                direction,
+                &hex_query,
+                &mut cursor_grid_move_event_w,
+            );
         }
     }
 }
 
+/// Hex direction pointing closest to `input`, alternating on ties so going straight up zigzags.
+fn closest_direction(input: Vec2, last: Option<HexDirection>) -> HexDirection {
+    let score = |direction: &HexDirection| {
+        input.dot(direction.to_axial().to_vec3_pixel().truncate().normalize())
+    };
+    let best = HexDirection::ALL.iter().map(score).fold(f32::MIN, f32::max);
+
+    let mut candidates = HexDirection::ALL
+        .into_iter()
+        .filter(|direction| best - score(direction) < 1e-3);
+    let first = candidates.next().unwrap_or(HexDirection::East);
+
+    candidates
+        .find(|direction| Some(first) == last && Some(*direction) != last)
+        .unwrap_or(first)
+}
+
+#[derive(Default)]
+struct GamepadRepeat {
+    timer: Timer,
+    last: Option<HexDirection>,
+}
+
+fn cursor_gamepad(
+    time: Res<Time>,
+    gamepads: Res<Gamepads>,
+    axes: Res<Axis<GamepadAxis>>,
+    actions: Res<ActionState>,
+    mut repeat: Local<GamepadRepeat>,
+    mut cursor_grid_move_event_w: EventWriter<CursorGridMoveEvent>,
+    mut cursor_grid_pos: ResMut<CursorGridPos>,
+    mut mouse_cursor: ResMut<MouseCursor>,
+    hex_query: Query<&HexPos, With<Hex>>,
+) {
+    let mut input = Vec2::ZERO;
+    let mut pressed = false;
+
+    for gamepad in gamepads.iter() {
+        let axis = |axis_type| axes.get(GamepadAxis::new(gamepad, axis_type)).unwrap_or(0.);
+        input += Vec2::new(
+            axis(GamepadAxisType::LeftStickX),
+            axis(GamepadAxisType::LeftStickY),
+        );
+    }
+
+    let dpad = [
+        (Action::CursorRight, Vec2::X),
+        (Action::CursorLeft, Vec2::NEG_X),
+        (Action::CursorUp, Vec2::Y),
+        (Action::CursorDown, Vec2::NEG_Y),
+    ];
+    for (action, direction) in dpad {
+        if actions.pressed(action) {
+            input += direction;
+        }
+        pressed |= actions.just_pressed(action);
+    }
+
+    if input.length() < GAMEPAD_DEADZONE {
+        repeat.last = None;
+        return;
+    }
+
+    repeat.timer.tick(time.delta());
+    if !pressed && repeat.last.is_some() && !repeat.timer.finished() {
+        return;
+    }
+
+    let direction = closest_direction(input.normalize(), repeat.last);
+    repeat.last = Some(direction);
+    repeat.timer = Timer::from_seconds(GAMEPAD_REPEAT, TimerMode::Once);
+
+    step_cursor(
+        &mut cursor_grid_pos,
+        &mut mouse_cursor,
+        direction,
+        &hex_query,
+        &mut cursor_grid_move_event_w,
+    );
+}
+
 fn cursor_click(
     mut cursor_grid_click_event_w: EventWriter<CursorGridClickEvent>,
     cursor_grid_pos: Res<CursorGridPos>,
-    buttons: Res<ButtonInput<MouseButton>>,
+    actions: Res<ActionState>,
 ) {
-    if buttons.just_released(MouseButton::Left) {
-        cursor_grid_click_event_w.send(CursorGridClickEvent {
-            pos: cursor_grid_pos.pos,
-        });
+    if let (true, Some(pos)) = (actions.just_released(Action::Select), cursor_grid_pos.pos) {
+        cursor_grid_click_event_w.send(CursorGridClickEvent { pos });
+    }
+}
+
+fn cursor_cancel(
+    mut cursor_cancel_event_w: EventWriter<CursorCancelEvent>,
+    actions: Res<ActionState>,
+) {
+    if actions.just_pressed(Action::Cancel) {
+        cursor_cancel_event_w.send(CursorCancelEvent);
+    }
+}
+
+fn update_cursor_sprite(
+    cursor_grid_pos: Res<CursorGridPos>,
+    mut cursor_query: Query<(&mut Transform, &mut Visibility), With<HexCursor>>,
+) {
+    if !cursor_grid_pos.is_changed() {
+        return;
+    }
+    let Ok((mut transform, mut visibility)) = cursor_query.get_single_mut() else {
+        return;
+    };
+
+    match cursor_grid_pos.pos {
+        Some(pos) => {
+            transform.translation = pos.to_vec3_pixel().set_z(10.);
+            *visibility = Visibility::Inherited;
+        }
+        None => *visibility = Visibility::Hidden,
     }
 }
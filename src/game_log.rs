@@ -0,0 +1,278 @@
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::Path,
+};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::{
+    bevy_egui::{EguiContexts, EguiSettings},
+    egui,
+};
+
+use crate::{
+    combat::{resolve_attack, unit_death, DamageDealtEvent, Health, UnitDiedEvent},
+    hex_pos::HexPos,
+    history::{RedoEvent, UndoEvent},
+    movement::{Mp, UnitMovedEvent},
+    schedule::UpdateSet,
+    turn::{Team, TurnEndedEvent, TurnStartedEvent},
+    unit::Unit,
+    Axial,
+};
+
+/// Something worth telling the player, names are resolved when it happens since units die.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GameLogEntry {
+    TurnStarted {
+        team: usize,
+        unit: Option<String>,
+    },
+    TurnEnded {
+        team: usize,
+    },
+    Moved {
+        unit: String,
+        to: Axial,
+        mp_left: usize,
+    },
+    Damage {
+        attacker: String,
+        target: String,
+        amount: usize,
+        hp_left: usize,
+    },
+    Died {
+        unit: String,
+        pos: Axial,
+    },
+    Undo,
+    Redo,
+}
+
+impl fmt::Display for GameLogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameLogEntry::TurnStarted { team, unit: None } => {
+                write!(f, "Team {} starts its turn", team)
+            }
+            GameLogEntry::TurnStarted {
+                team,
+                unit: Some(unit),
+            } => write!(f, "{} (team {}) starts its turn", unit, team),
+            GameLogEntry::TurnEnded { team } => write!(f, "Team {} ends its turn", team),
+            GameLogEntry::Moved { unit, to, mp_left } => write!(
+                f,
+                "{} moves to ({}, {}), {} MP left",
+                unit, to.q, to.r, mp_left
+            ),
+            GameLogEntry::Damage {
+                attacker,
+                target,
+                amount,
+                hp_left,
+            } => write!(
+                f,
+                "{} hits {} for {} damage, {} HP left",
+                attacker, target, amount, hp_left
+            ),
+            GameLogEntry::Died { unit, pos } => {
+                write!(f, "{} dies at ({}, {})", unit, pos.q, pos.r)
+            }
+            GameLogEntry::Undo => write!(f, "Last action undone"),
+            GameLogEntry::Redo => write!(f, "Last action redone"),
+        }
+    }
+}
+
+#[derive(Resource, Debug, Default)]
+pub struct GameLog {
+    pub entries: Vec<GameLogEntry>,
+}
+
+/// File the log is mirrored to, `written` entries are already in it.
+#[derive(Resource, Debug)]
+struct GameLogFile {
+    path: String,
+    written: usize,
+}
+
+/// Keeps a `GameLog` of the match, also written to `path` one line per entry when set.
+#[derive(Default)]
+pub struct GameLogPlugin {
+    pub path: Option<String>,
+}
+
+impl Plugin for GameLogPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameLog>().add_systems(
+            Update,
+            (
+                // damage is read before `unit_death` despawns its target
+                record_events
+                    .after(resolve_attack)
+                    .before(unit_death)
+                    .in_set(UpdateSet::Combat),
+                write_game_log
+                    .run_if(resource_exists::<GameLogFile>)
+                    .after(UpdateSet::Combat),
+                game_log_panel.run_if(resource_exists::<EguiSettings>),
+            ),
+        );
+
+        if let Some(path) = &self.path {
+            match create_log_file(path) {
+                Ok(()) => {
+                    app.insert_resource(GameLogFile {
+                        path: path.clone(),
+                        written: 0,
+                    });
+                }
+                Err(error) => error!("could not create game log {}: {}", path, error),
+            }
+        }
+    }
+}
+
+fn create_log_file(path: &str) -> Result<(), String> {
+    if let Some(parent) = Path::new(path).parent() {
+        fs::create_dir_all(parent).map_err(|error| error.to_string())?;
+    }
+    File::create(path)
+        .map(|_| ())
+        .map_err(|error| error.to_string())
+}
+
+fn unit_label(name: &Name, team: &Team) -> String {
+    format!("{} (team {})", name, team.id)
+}
+
+fn record_events(
+    mut game_log: ResMut<GameLog>,
+    mut turn_ended_event_r: EventReader<TurnEndedEvent>,
+    mut turn_started_event_r: EventReader<TurnStartedEvent>,
+    mut unit_moved_event_r: EventReader<UnitMovedEvent>,
+    mut damage_dealt_event_r: EventReader<DamageDealtEvent>,
+    mut unit_died_event_r: EventReader<UnitDiedEvent>,
+    mut undo_event_r: EventReader<UndoEvent>,
+    mut redo_event_r: EventReader<RedoEvent>,
+    unit_query: Query<(&Name, &Team, &HexPos, &Mp, &Health), With<Unit>>,
+) {
+    let describe = |entity: Entity| match unit_query.get(entity) {
+        Ok((name, team, _, _, _)) => unit_label(name, team),
+        Err(_) => "Unit".to_string(),
+    };
+    let entries = &mut game_log.entries;
+
+    for _ in undo_event_r.read() {
+        entries.push(GameLogEntry::Undo);
+    }
+    for _ in redo_event_r.read() {
+        entries.push(GameLogEntry::Redo);
+    }
+    for event in turn_ended_event_r.read() {
+        entries.push(GameLogEntry::TurnEnded { team: event.team });
+    }
+    for event in turn_started_event_r.read() {
+        entries.push(GameLogEntry::TurnStarted {
+            team: event.team,
+            unit: event
+                .unit
+                .and_then(|unit| unit_query.get(unit).ok())
+                .map(|(name, _, _, _, _)| name.to_string()),
+        });
+    }
+    for event in unit_moved_event_r.read() {
+        if let Ok((_, _, hex_pos, mp, _)) = unit_query.get(event.entity) {
+            entries.push(GameLogEntry::Moved {
+                unit: describe(event.entity),
+                to: hex_pos.pos,
+                mp_left: mp.current,
+            });
+        }
+    }
+    for event in damage_dealt_event_r.read() {
+        entries.push(GameLogEntry::Damage {
+            attacker: describe(event.attacker),
+            target: describe(event.target),
+            amount: event.amount,
+            hp_left: unit_query
+                .get(event.target)
+                .map_or(0, |(_, _, _, _, health)| health.current),
+        });
+    }
+    for event in unit_died_event_r.read() {
+        entries.push(GameLogEntry::Died {
+            unit: unit_label(&event.name, &event.team),
+            pos: event.pos,
+        });
+    }
+}
+
+fn write_game_log(game_log: Res<GameLog>, mut game_log_file: ResMut<GameLogFile>) {
+    if game_log_file.written == game_log.entries.len() {
+        return;
+    }
+
+    let lines = game_log.entries[game_log_file.written..]
+        .iter()
+        .map(|entry| format!("{}\n", entry))
+        .collect::<String>();
+    let written = OpenOptions::new()
+        .append(true)
+        .open(&game_log_file.path)
+        .and_then(|mut file| file.write_all(lines.as_bytes()));
+
+    if let Err(error) = written {
+        error!(
+            "could not write game log to {}: {}",
+            game_log_file.path, error
+        );
+    }
+    // skipped on failure too, retrying every frame would only flood the output
+    game_log_file.written = game_log.entries.len();
+}
+
+fn game_log_panel(mut contexts: EguiContexts, game_log: Res<GameLog>) {
+    egui::TopBottomPanel::bottom("game_log")
+        .resizable(true)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(120.)
+                .stick_to_bottom(true)
+                .auto_shrink([false, true])
+                .show(ui, |ui| {
+                    for entry in &game_log.entries {
+                        ui.label(entry.to_string());
+                    }
+                });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{combat::AttackEvent, test_harness::TestApp};
+
+    #[test]
+    fn killing_blow_names_the_target() {
+        let mut game = TestApp::new();
+        let attacker = game.unit_at(Axial::new(0, 0)).unwrap();
+        let target = game.unit_at(Axial::new(1, -1)).unwrap();
+        game.app.world.get_mut::<Health>(target).unwrap().current = 1;
+
+        game.app.world.send_event(AttackEvent { attacker, target });
+        game.advance(2);
+
+        let entries = &game.app.world.resource::<GameLog>().entries;
+        assert!(entries.iter().any(|entry| matches!(
+            entry,
+            GameLogEntry::Damage { target, hp_left: 0, .. } if target == "Unit 3 (team 1)"
+        )));
+        assert!(entries.contains(&GameLogEntry::Died {
+            unit: "Unit 3 (team 1)".to_string(),
+            pos: Axial::new(1, -1),
+        }));
+    }
+}
@@ -1,8 +1,229 @@
-use bevy::{prelude::*, transform::commands};
-use bevy_inspector_egui::bevy_egui::EguiContext;
+use bevy::{ecs::query::ROQueryItem, prelude::*};
+use bevy_inspector_egui::{
+    bevy_egui::{EguiContexts, EguiSettings},
+    egui,
+};
+
+use crate::{
+    camera::MainCamera,
+    combat::{Attack, Health},
+    cursor::CursorGridPos,
+    hex_pos::HexPos,
+    movement::{MovementAnimation, Mp},
+    schedule::GameState,
+    turn::{Team, TurnManager, TurnMode, TurnQueue},
+    unit::{CurrentActiveUnit, Unit},
+};
 
 pub struct GuiPlugin;
 
 impl Plugin for GuiPlugin {
-    fn build(&self, app: &mut App) {}
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            // the top bar goes first so it spans the whole window
+            (turn_order_bar, unit_info_panel)
+                .chain()
+                .run_if(resource_exists::<EguiSettings>)
+                .run_if(in_state(GameState::InGame)),
+        );
+    }
+}
+
+/// Matches the unit materials picked in `spawn_unit`.
+fn team_color(team: &Team) -> egui::Color32 {
+    match team.id {
+        0 => egui::Color32::RED,
+        1 => egui::Color32::LIGHT_BLUE,
+        _ => egui::Color32::GRAY,
+    }
+}
+
+type UnitInfo = (
+    &'static Name,
+    &'static Team,
+    &'static Health,
+    &'static Mp,
+    &'static HexPos,
+    Option<&'static Attack>,
+    Has<MovementAnimation>,
+);
+
+/// Conditions read off the unit components, there are no dedicated status effects yet.
+fn status_effects(unit: &ROQueryItem<UnitInfo>) -> Vec<&'static str> {
+    let (_, _, health, mp, _, attack, moving) = unit;
+    let mut effects = Vec::new();
+
+    if *moving {
+        effects.push("Moving");
+    }
+    if attack.is_some_and(|attack| attack.spent) {
+        effects.push("Attacked");
+    }
+    if mp.current == 0 {
+        effects.push("Exhausted");
+    }
+    if health.current * 4 <= health.max {
+        effects.push("Wounded");
+    }
+
+    effects
+}
+
+fn unit_info(ui: &mut egui::Ui, title: &str, unit: &ROQueryItem<UnitInfo>) {
+    let (name, team, health, mp, hex_pos, _, _) = unit;
+    let effects = status_effects(unit);
+
+    ui.heading(title);
+    egui::Grid::new(title).num_columns(2).show(ui, |ui| {
+        ui.label("Name");
+        ui.label(name.as_str());
+        ui.end_row();
+
+        ui.label("Team");
+        ui.label(team.id.to_string());
+        ui.end_row();
+
+        ui.label("HP");
+        ui.label(format!("{}/{}", health.current, health.max));
+        ui.end_row();
+
+        ui.label("MP");
+        ui.label(format!("{}/{}", mp.current, mp.base));
+        ui.end_row();
+
+        ui.label("Position");
+        ui.label(format!("q: {}, r: {}", hex_pos.pos.q, hex_pos.pos.r));
+        ui.end_row();
+
+        ui.label("Status");
+        if effects.is_empty() {
+            ui.label("None");
+        } else {
+            ui.label(effects.join(", "));
+        }
+        ui.end_row();
+    });
+}
+
+fn unit_info_panel(
+    mut contexts: EguiContexts,
+    selected_unit: Res<CurrentActiveUnit>,
+    cursor_grid_pos: Res<CursorGridPos>,
+    unit_query: Query<(Entity, UnitInfo), With<Unit>>,
+) {
+    let selected = selected_unit
+        .entity
+        .and_then(|entity| unit_query.get(entity).ok());
+    let hovered = cursor_grid_pos.pos.and_then(|pos| {
+        unit_query
+            .iter()
+            .find(|(_, (_, _, _, _, hex_pos, _, _))| hex_pos.pos == pos)
+    });
+
+    egui::SidePanel::left("unit_info")
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            match &selected {
+                Some((_, unit)) => unit_info(ui, "Selected", unit),
+                None => {
+                    ui.heading("Selected");
+                    ui.label("No unit selected");
+                }
+            }
+
+            if let Some((entity, unit)) = &hovered {
+                if selected.as_ref().map(|(selected, _)| selected) != Some(entity) {
+                    ui.separator();
+                    unit_info(ui, "Hovered", unit);
+                }
+            }
+        });
+}
+
+/// Units in the order they will play, starting with the ones whose turn it is.
+fn turn_order(
+    turn_mode: &TurnMode,
+    turn_manager: &TurnManager,
+    turn_queue: &TurnQueue,
+    units: &[(Entity, &Team)],
+) -> Vec<(Entity, bool)> {
+    match turn_mode {
+        TurnMode::Teams => {
+            let teams = turn_manager.teams.len();
+
+            (0..teams)
+                .map(|offset| turn_manager.teams[(turn_manager.active + offset) % teams])
+                .flat_map(|team| {
+                    let active = turn_manager.active_team() == Some(team);
+                    units
+                        .iter()
+                        .filter(move |(_, unit_team)| unit_team.id == team)
+                        .map(move |(entity, _)| (*entity, active))
+                })
+                .collect()
+        }
+        TurnMode::Initiative => turn_queue
+            .queue
+            .iter()
+            .enumerate()
+            .map(|(index, entity)| (*entity, index == 0))
+            .collect(),
+    }
+}
+
+fn turn_order_bar(
+    mut contexts: EguiContexts,
+    turn_mode: Res<TurnMode>,
+    turn_manager: Res<TurnManager>,
+    turn_queue: Res<TurnQueue>,
+    cursor_grid_pos: Res<CursorGridPos>,
+    unit_query: Query<(Entity, &Name, &Team, &Health, &HexPos), With<Unit>>,
+    mut camera_query: Query<&mut Transform, With<MainCamera>>,
+) {
+    let mut units = unit_query
+        .iter()
+        .map(|(entity, _, team, _, _)| (entity, team))
+        .collect::<Vec<(Entity, &Team)>>();
+    units.sort_by_key(|(entity, _)| *entity);
+    let order = turn_order(&turn_mode, &turn_manager, &turn_queue, &units);
+
+    let hovered = cursor_grid_pos.pos.and_then(|pos| {
+        unit_query
+            .iter()
+            .find(|(_, _, _, _, hex_pos)| hex_pos.pos == pos)
+            .map(|(entity, _, _, _, _)| entity)
+    });
+
+    egui::TopBottomPanel::top("turn_order").show(contexts.ctx_mut(), |ui| {
+        // the round and who is playing are in the Turn window
+        egui::ScrollArea::horizontal().show(ui, |ui| {
+            ui.horizontal(|ui| {
+                for (entity, active) in order {
+                    let Ok((_, name, team, health, hex_pos)) = unit_query.get(entity) else {
+                        continue;
+                    };
+
+                    let mut text = egui::RichText::new(format!(
+                        "{}\n{}/{} HP",
+                        name, health.current, health.max
+                    ))
+                    .color(team_color(team));
+                    if active {
+                        text = text.strong();
+                    }
+
+                    let portrait =
+                        ui.add(egui::SelectableLabel::new(hovered == Some(entity), text));
+                    if portrait.clicked() {
+                        if let Ok(mut transform) = camera_query.get_single_mut() {
+                            let z = transform.translation.z;
+                            transform.translation =
+                                hex_pos.pos.to_vec3_pixel().truncate().extend(z);
+                        }
+                    }
+                }
+            });
+        });
+    });
 }
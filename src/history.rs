@@ -0,0 +1,154 @@
+use bevy::prelude::*;
+
+use crate::{
+    hex_pos::HexPos,
+    input::{Action, ActionState},
+    movement::{movement_idle, Mp, UnitMovedEvent},
+    replay::{not_replaying, CommandAppliedEvent, ReplayAction},
+    schedule::UpdateSet,
+    unit::{CurrentActiveUnit, Unit},
+    Axial, Vec3Extra,
+};
+
+/// Gameplay action as recorded in the `CommandHistory`.
+#[derive(Debug, Clone)]
+pub enum GameCommand {
+    MoveUnit {
+        entity: Entity,
+        from: Axial,
+        to: Axial,
+        cost: usize,
+    },
+    EndTurn,
+    /// Only kept as an undo barrier, the damage itself is in the `DamageDealtEvent`.
+    Attack,
+}
+
+impl GameCommand {
+    /// Only moves can be taken back, ending a turn or rolling damage is final.
+    pub fn is_undoable(&self) -> bool {
+        matches!(self, GameCommand::MoveUnit { .. })
+    }
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct CommandHistory {
+    pub done: Vec<GameCommand>,
+    pub undone: Vec<GameCommand>,
+}
+
+impl CommandHistory {
+    pub fn push(&mut self, command: GameCommand) {
+        self.done.push(command);
+        self.undone.clear();
+    }
+
+    fn pop_undoable(&mut self) -> Option<GameCommand> {
+        if self.done.last()?.is_undoable() {
+            self.done.pop()
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Event, Debug)]
+pub struct UndoEvent;
+
+#[derive(Event, Debug)]
+pub struct RedoEvent;
+
+pub struct HistoryPlugin;
+
+impl Plugin for HistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CommandHistory>()
+            .add_event::<UndoEvent>()
+            .add_event::<RedoEvent>()
+            .add_systems(
+                Update,
+                (undo_redo_keys.run_if(not_replaying), undo_redo)
+                    .chain()
+                    .run_if(movement_idle)
+                    .in_set(UpdateSet::UserInput),
+            );
+    }
+}
+
+fn undo_redo_keys(
+    actions: Res<ActionState>,
+    mut undo_event_w: EventWriter<UndoEvent>,
+    mut redo_event_w: EventWriter<RedoEvent>,
+) {
+    if actions.just_pressed(Action::Undo) {
+        undo_event_w.send(UndoEvent);
+    } else if actions.just_pressed(Action::Redo) {
+        redo_event_w.send(RedoEvent);
+    }
+}
+
+fn undo_redo(
+    mut undo_event_r: EventReader<UndoEvent>,
+    mut redo_event_r: EventReader<RedoEvent>,
+    mut history: ResMut<CommandHistory>,
+    mut command_applied_event_w: EventWriter<CommandAppliedEvent>,
+    mut selected_unit: ResMut<CurrentActiveUnit>,
+    mut unit_moved_event_w: EventWriter<UnitMovedEvent>,
+    mut unit_query: Query<(&mut HexPos, &mut Mp, &mut Transform), With<Unit>>,
+) {
+    let requests = undo_event_r
+        .read()
+        .map(|_| false)
+        .chain(redo_event_r.read().map(|_| true))
+        .collect::<Vec<bool>>();
+
+    for forward in requests {
+        let command = if forward {
+            history.undone.pop()
+        } else {
+            history.pop_undoable()
+        };
+
+        let Some(command) = command else {
+            continue;
+        };
+        command_applied_event_w.send(CommandAppliedEvent {
+            action: if forward {
+                ReplayAction::Redo
+            } else {
+                ReplayAction::Undo
+            },
+        });
+
+        if let GameCommand::MoveUnit {
+            entity,
+            from,
+            to,
+            cost,
+        } = command
+        {
+            let Ok((mut hex_pos, mut mp, mut transform)) = unit_query.get_mut(entity) else {
+                continue;
+            };
+
+            let destination = if forward {
+                mp.current -= cost;
+                to
+            } else {
+                mp.current += cost;
+                from
+            };
+            hex_pos.pos = destination;
+            transform.translation = destination.to_vec3_pixel().set_z(2.);
+
+            selected_unit.entity = Some(entity);
+            unit_moved_event_w.send(UnitMovedEvent { entity });
+        }
+
+        if forward {
+            history.done.push(command);
+        } else {
+            history.undone.push(command);
+        }
+    }
+}
@@ -0,0 +1,491 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use bevy::{input::InputSystem, prelude::*};
+use bevy_inspector_egui::{
+    bevy_egui::{EguiContexts, EguiSet, EguiSettings},
+    egui,
+};
+use serde::{Deserialize, Serialize};
+
+/// Shipped bindings, embedded so they do not depend on the working directory.
+const DEFAULT_BINDINGS: &str = include_str!("../assets/input.ron");
+/// Player changes, preferred over the defaults when present.
+const USER_BINDINGS_PATH: &str = "settings/input.ron";
+
+/// What the player wants done, independently of the device used to ask for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Action {
+    Select,
+    Cancel,
+    EndTurn,
+    Attack,
+    Undo,
+    Redo,
+    QuickSave,
+    QuickLoad,
+    PanUp,
+    PanDown,
+    PanLeft,
+    PanRight,
+    /// Held while dragging the view around.
+    PanCamera,
+    ZoomIn,
+    ZoomOut,
+    CursorEast,
+    CursorNorthEast,
+    CursorNorthWest,
+    CursorWest,
+    CursorSouthWest,
+    CursorSouthEast,
+    /// Screen directions, the cursor picks the closest hex direction.
+    CursorUp,
+    CursorDown,
+    CursorLeft,
+    CursorRight,
+    ReplayPause,
+    ReplayStep,
+    ReplayFastForward,
+    Controls,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Binding {
+    /// Key pressed without Ctrl.
+    Key(KeyCode),
+    /// Key pressed while holding Ctrl but not Shift.
+    Ctrl(KeyCode),
+    CtrlShift(KeyCode),
+    Mouse(MouseButton),
+    /// Button of any connected gamepad.
+    Gamepad(GamepadButtonType),
+}
+
+impl Binding {
+    fn is_modifier(key: KeyCode) -> bool {
+        matches!(
+            key,
+            KeyCode::ControlLeft | KeyCode::ControlRight | KeyCode::ShiftLeft | KeyCode::ShiftRight
+        )
+    }
+
+    /// Whether the binding is (pressed, just pressed, just released) this frame.
+    fn state(&self, inputs: &RawInputs) -> (bool, bool, bool) {
+        let ctrl = inputs
+            .keys
+            .any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+        let shift = inputs
+            .keys
+            .any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+
+        let (modifiers, key) = match *self {
+            Binding::Key(key) => (!ctrl, key),
+            Binding::Ctrl(key) => (ctrl && !shift, key),
+            Binding::CtrlShift(key) => (ctrl && shift, key),
+            // presses started on the UI are not meant for the map underneath
+            Binding::Mouse(button) if inputs.ui_presses.contains(&button) => {
+                return (false, false, false)
+            }
+            Binding::Mouse(button) => return button_state(inputs.mouse_buttons, button),
+            Binding::Gamepad(button_type) => {
+                return inputs
+                    .gamepads
+                    .iter()
+                    .map(|gamepad| {
+                        button_state(
+                            inputs.gamepad_buttons,
+                            GamepadButton::new(gamepad, button_type),
+                        )
+                    })
+                    .fold((false, false, false), |a, b| {
+                        (a.0 || b.0, a.1 || b.1, a.2 || b.2)
+                    })
+            }
+        };
+
+        let (pressed, just_pressed, just_released) = button_state(inputs.keys, key);
+        (
+            pressed && modifiers,
+            just_pressed && modifiers,
+            just_released,
+        )
+    }
+}
+
+fn button_state<T: Copy + Eq + std::hash::Hash + Send + Sync>(
+    input: &ButtonInput<T>,
+    button: T,
+) -> (bool, bool, bool) {
+    (
+        input.pressed(button),
+        input.just_pressed(button),
+        input.just_released(button),
+    )
+}
+
+struct RawInputs<'a> {
+    keys: &'a ButtonInput<KeyCode>,
+    mouse_buttons: &'a ButtonInput<MouseButton>,
+    gamepads: &'a Gamepads,
+    gamepad_buttons: &'a ButtonInput<GamepadButton>,
+    ui_presses: &'a HashSet<MouseButton>,
+}
+
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputMap {
+    pub bindings: BTreeMap<Action, Vec<Binding>>,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        ron::de::from_str(DEFAULT_BINDINGS).expect("assets/input.ron should be a valid input map")
+    }
+}
+
+impl InputMap {
+    pub fn read(path: &str) -> Result<InputMap, String> {
+        let content = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        ron::de::from_str::<InputMap>(&content).map_err(|error| error.to_string())
+    }
+
+    pub fn write(&self, path: &str) -> Result<(), String> {
+        let content = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|error| error.to_string())?;
+
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent).map_err(|error| error.to_string())?;
+        }
+        fs::write(path, content).map_err(|error| error.to_string())
+    }
+
+    /// Player bindings if saved at `path`, else the shipped defaults.
+    pub fn load(path: Option<&str>) -> InputMap {
+        if let Some(path) = path.filter(|path| Path::new(path).exists()) {
+            match InputMap::read(path) {
+                Ok(input_map) => return input_map,
+                Err(error) => warn!("could not read {}: {}", path, error),
+            }
+        }
+
+        InputMap::default()
+    }
+
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        let bindings = self.bindings.entry(action).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+}
+
+/// Actions triggered this frame, what gameplay systems read instead of devices.
+#[derive(Resource, Default, Debug)]
+pub struct ActionState {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+    just_released: HashSet<Action>,
+    pointer_over_ui: bool,
+}
+
+impl ActionState {
+    /// Whether egui wants the pointer, mouse presses starting there are ignored.
+    pub fn pointer_over_ui(&self) -> bool {
+        self.pointer_over_ui
+    }
+
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    pub fn just_released(&self, action: Action) -> bool {
+        self.just_released.contains(&action)
+    }
+}
+
+/// Whether egui was using the pointer on the last frame, headless games never set it.
+#[derive(Resource, Default, Debug)]
+struct PointerOverUi(bool);
+
+/// Where player bindings are read from and saved to, `None` sticks to the shipped defaults.
+#[derive(Resource, Debug)]
+struct BindingsPath(Option<String>);
+
+/// Controls window state, `rebinding` waits for the next input to bind it.
+#[derive(Resource, Default, Debug)]
+pub struct ControlsMenu {
+    pub open: bool,
+    pub rebinding: Option<Action>,
+}
+
+pub struct InputMapPlugin {
+    /// Player bindings file, overriding the shipped ones.
+    pub settings_path: Option<String>,
+}
+
+impl Default for InputMapPlugin {
+    fn default() -> Self {
+        Self {
+            settings_path: Some(USER_BINDINGS_PATH.to_string()),
+        }
+    }
+}
+
+impl Plugin for InputMapPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(InputMap::load(self.settings_path.as_deref()))
+            .insert_resource(BindingsPath(self.settings_path.clone()))
+            .init_resource::<ActionState>()
+            .init_resource::<ControlsMenu>()
+            .init_resource::<PointerOverUi>()
+            .add_systems(
+                PreUpdate,
+                (
+                    update_pointer_over_ui
+                        .run_if(resource_exists::<EguiSettings>)
+                        .after(EguiSet::InitContexts),
+                    update_action_state,
+                    capture_binding,
+                )
+                    .chain()
+                    .after(InputSystem),
+            )
+            .add_systems(
+                Update,
+                controls_window.run_if(resource_exists::<EguiSettings>),
+            );
+    }
+}
+
+fn update_pointer_over_ui(mut contexts: EguiContexts, mut pointer_over_ui: ResMut<PointerOverUi>) {
+    pointer_over_ui.0 = contexts.ctx_mut().wants_pointer_input();
+}
+
+fn update_action_state(
+    input_map: Res<InputMap>,
+    controls_menu: Res<ControlsMenu>,
+    pointer_over_ui: Res<PointerOverUi>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<ButtonInput<GamepadButton>>,
+    mut action_state: ResMut<ActionState>,
+    mut ui_presses: Local<HashSet<MouseButton>>,
+) {
+    *action_state = ActionState {
+        pointer_over_ui: pointer_over_ui.0,
+        ..default()
+    };
+
+    // a press belongs to where it started, so a map drag goes on over a panel and not the other way
+    ui_presses.retain(|&button| {
+        let held = mouse_buttons.pressed(button) && !mouse_buttons.just_pressed(button);
+        held || mouse_buttons.just_released(button)
+    });
+    if pointer_over_ui.0 {
+        ui_presses.extend(mouse_buttons.get_just_pressed().copied());
+    }
+
+    // the input is meant for the binding being edited
+    if controls_menu.rebinding.is_some() {
+        return;
+    }
+
+    let inputs = RawInputs {
+        keys: &keys,
+        mouse_buttons: &mouse_buttons,
+        gamepads: &gamepads,
+        gamepad_buttons: &gamepad_buttons,
+        ui_presses: &ui_presses,
+    };
+
+    for (&action, bindings) in &input_map.bindings {
+        for binding in bindings {
+            let (pressed, just_pressed, just_released) = binding.state(&inputs);
+
+            if pressed {
+                action_state.pressed.insert(action);
+            }
+            if just_pressed {
+                action_state.just_pressed.insert(action);
+            }
+            if just_released {
+                action_state.just_released.insert(action);
+            }
+        }
+    }
+}
+
+fn capture_binding(
+    mut controls_menu: ResMut<ControlsMenu>,
+    mut input_map: ResMut<InputMap>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    gamepad_buttons: Res<ButtonInput<GamepadButton>>,
+) {
+    let Some(action) = controls_menu.rebinding else {
+        return;
+    };
+
+    let ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let key = keys
+        .get_just_pressed()
+        .find(|key| !Binding::is_modifier(**key))
+        .map(|&key| match (ctrl, shift) {
+            (true, true) => Binding::CtrlShift(key),
+            (true, false) => Binding::Ctrl(key),
+            _ => Binding::Key(key),
+        });
+    // buttons count when pressed, so the release of the click on "Add" is not taken
+    let mouse = mouse_buttons
+        .get_just_pressed()
+        .next()
+        .map(|&button| Binding::Mouse(button));
+    let gamepad = gamepad_buttons
+        .get_just_pressed()
+        .next()
+        .map(|button| Binding::Gamepad(button.button_type));
+
+    if let Some(binding) = key.or(mouse).or(gamepad) {
+        input_map.bind(action, binding);
+        controls_menu.rebinding = None;
+    }
+}
+
+fn controls_window(
+    mut contexts: EguiContexts,
+    actions: Res<ActionState>,
+    mut controls_menu: ResMut<ControlsMenu>,
+    mut input_map: ResMut<InputMap>,
+    bindings_path: Res<BindingsPath>,
+) {
+    if actions.just_pressed(Action::Controls) {
+        controls_menu.open = !controls_menu.open;
+    }
+    if !controls_menu.open {
+        return;
+    }
+
+    let mut actions = input_map.bindings.keys().copied().collect::<Vec<Action>>();
+    actions.sort();
+
+    let mut open = true;
+    egui::Window::new("Controls")
+        .open(&mut open)
+        .collapsible(false)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(400.)
+                .show(ui, |ui| {
+                    egui::Grid::new("bindings").striped(true).show(ui, |ui| {
+                        for action in actions {
+                            ui.label(format!("{:?}", action));
+
+                            let bindings = input_map.bindings[&action]
+                                .iter()
+                                .map(|binding| format!("{:?}", binding))
+                                .collect::<Vec<String>>()
+                                .join(", ");
+                            ui.label(bindings);
+
+                            if controls_menu.rebinding == Some(action) {
+                                if ui.button("Press an input...").clicked() {
+                                    controls_menu.rebinding = None;
+                                }
+                            } else if ui.button("Add").clicked() {
+                                controls_menu.rebinding = Some(action);
+                            }
+                            if ui.button("Clear").clicked() {
+                                input_map.bindings.insert(action, Vec::new());
+                            }
+                            ui.end_row();
+                        }
+                    });
+                });
+
+            ui.horizontal(|ui| {
+                if let Some(path) = &bindings_path.0 {
+                    if ui.button("Save").clicked() {
+                        match input_map.write(path) {
+                            Ok(()) => info!("bindings saved to {}", path),
+                            Err(error) => error!("could not save bindings to {}: {}", path, error),
+                        }
+                    }
+                }
+                if ui.button("Reset to defaults").clicked() {
+                    *input_map = InputMap::default();
+                }
+            });
+        });
+
+    controls_menu.open = open;
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::input::{mouse::MouseButtonInput, ButtonState};
+
+    use super::*;
+    use crate::test_harness::TestApp;
+
+    #[test]
+    fn default_bindings_trigger_one_action_each() {
+        let input_map = InputMap::default();
+        let mut seen = HashSet::new();
+
+        for (action, bindings) in &input_map.bindings {
+            for binding in bindings {
+                assert!(
+                    seen.insert(*binding),
+                    "{:?} is bound twice, on {:?}",
+                    binding,
+                    action
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn headless_games_ignore_player_bindings() {
+        let app = TestApp::new();
+
+        assert!(app.app.world.resource::<BindingsPath>().0.is_none());
+        assert_eq!(*app.app.world.resource::<InputMap>(), InputMap::default());
+    }
+
+    fn press_middle(app: &mut TestApp, state: ButtonState, over_ui: bool) -> bool {
+        app.app.world.resource_mut::<PointerOverUi>().0 = over_ui;
+        app.app.world.send_event(MouseButtonInput {
+            button: MouseButton::Middle,
+            state,
+            window: Entity::PLACEHOLDER,
+        });
+        app.update();
+        app.app
+            .world
+            .resource::<ActionState>()
+            .pressed(Action::PanCamera)
+    }
+
+    #[test]
+    fn drags_belong_to_where_they_started() {
+        let mut app = TestApp::new();
+
+        assert!(press_middle(&mut app, ButtonState::Pressed, false));
+        assert!(press_middle(&mut app, ButtonState::Pressed, true));
+        assert!(!press_middle(&mut app, ButtonState::Released, true));
+
+        assert!(!press_middle(&mut app, ButtonState::Pressed, true));
+        assert!(!press_middle(&mut app, ButtonState::Pressed, false));
+        assert!(!press_middle(&mut app, ButtonState::Released, false));
+
+        assert!(press_middle(&mut app, ButtonState::Pressed, false));
+    }
+}
@@ -1,65 +1,195 @@
+// bevy systems take their queries and resources as arguments
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
+
 mod asset_loader;
+mod camera;
+mod combat;
 mod cursor;
+mod game_log;
 mod gui;
 mod hex_pos;
+mod history;
+mod input;
 mod map;
 mod movement;
 mod obstacle;
+mod path_preview;
+mod replay;
+mod rng;
+mod save;
 mod schedule;
+#[cfg(test)]
+mod test_harness;
+mod turn;
 mod unit;
 
-use std::ops::Add;
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+    fmt,
+    ops::{Add, RangeInclusive, Sub},
+};
 
 use bevy::{
+    app::PluginGroupBuilder,
     log::LogPlugin,
     prelude::*,
-    window::{PrimaryWindow, WindowResolution},
+    window::{ExitCondition, PrimaryWindow, WindowResolution},
 };
 use bevy_inspector_egui::{
     bevy_egui::{EguiContext, EguiPlugin},
     egui,
-    quick::WorldInspectorPlugin,
 };
-use map::Hex;
+use serde::{Deserialize, Serialize};
 
 fn main() {
+    // a replay brings its own seed, map and turn mode so the match plays out the same
+    // a broken replay falls back to a normal game, the error is logged once logging is up
+    let (replay, replay_error) = match std::env::var("TACTICS_REPLAY") {
+        Ok(path) => match replay::Replay::read(&path) {
+            Ok(replay) => (Some(replay), None),
+            Err(error) => (
+                None,
+                Some(format!("could not read replay {}: {}", path, error)),
+            ),
+        },
+        Err(_) => (None, None),
+    };
+    let seed = match &replay {
+        Some(replay) => Some(replay.seed),
+        None => std::env::var("TACTICS_SEED")
+            .ok()
+            .and_then(|seed| seed.parse().ok()),
+    };
+    let map_plugin = match &replay {
+        Some(replay) => map::MapPlugin {
+            path: replay.map.clone(),
+            shape: replay.shape.clone(),
+        },
+        None => map::MapPlugin::default(),
+    };
+    let turn_plugin = turn::TurnPlugin {
+        mode: replay
+            .as_ref()
+            .map_or(turn::TurnMode::default(), |replay| replay.mode),
+    };
+
+    let gameplay_plugins = |plugins: PluginGroupBuilder| {
+        plugins
+            .set(rng::RngPlugin { seed })
+            .set(map_plugin)
+            .set(turn_plugin)
+            .set(replay::ReplayPlugin {
+                playback: replay,
+                ..default()
+            })
+            .set(game_log::GameLogPlugin {
+                path: std::env::var("TACTICS_LOG").ok(),
+            })
+    };
+
     let mut app = App::new();
-    app.add_plugins(
-        DefaultPlugins
-            .set(ImagePlugin::default_nearest())
-            .set(WindowPlugin {
-                primary_window: Some(Window {
-                    resolution: WindowResolution::new(
-                        WINDOW.w * WINDOW.zoom,
-                        WINDOW.h * WINDOW.zoom,
-                    )
-                    .with_scale_factor_override(WINDOW.zoom),
-                    resizable: true,
+    if std::env::var("TACTICS_HEADLESS").is_ok() {
+        app.add_plugins(LogPlugin::default())
+            .add_plugins(gameplay_plugins(HeadlessPlugins.build()));
+    } else {
+        app.add_plugins(
+            DefaultPlugins
+                .set(ImagePlugin::default_nearest())
+                .set(WindowPlugin {
+                    primary_window: Some(Window {
+                        resolution: WindowResolution::new(
+                            WINDOW.w * WINDOW.zoom,
+                            WINDOW.h * WINDOW.zoom,
+                        )
+                        .with_scale_factor_override(WINDOW.zoom),
+                        resizable: true,
+                        ..default()
+                    }),
                     ..default()
                 }),
-                ..default()
-            }),
-    )
-    .add_systems(PostStartup, spawn_camera)
-    .add_plugins(EguiPlugin)
-    // .add_plugins(bevy_inspector_egui::DefaultInspectorConfigPlugin) // adds default options and `InspectorEguiImpl`s
-    // .add_systems(Update, inspector_ui)
-    .add_plugins(asset_loader::AssetLoaderPlugin)
-    .add_plugins(map::MapPlugin)
-    .add_plugins(obstacle::ObstaclePlugin)
-    .add_plugins(hex_pos::PositionPlugin)
-    .add_plugins(unit::UnitPlugin)
-    .add_plugins(schedule::SchedulePlugin)
-    .add_plugins(cursor::CursorPlugin)
-    .add_plugins(movement::MovementPlugin)
-    .run();
+        )
+        .add_plugins(camera::CameraPlugin)
+        .add_plugins(EguiPlugin)
+        .add_plugins(gui::GuiPlugin)
+        // .add_plugins(bevy_inspector_egui::DefaultInspectorConfigPlugin) // adds default options and `InspectorEguiImpl`s
+        // .add_systems(Update, inspector_ui)
+        .add_plugins(asset_loader::AssetLoaderPlugin)
+        .add_plugins(gameplay_plugins(GameplayPlugins.build()));
+    }
+    if let Some(error) = replay_error {
+        app.add_systems(Startup, move || error!("{}", error));
+    }
+    app.run();
     // bevy_mod_debugdump::print_schedule_graph(&mut app, PostUpdate);
 }
 
-fn spawn_camera(mut commands: Commands) {
-    commands.spawn(Camera2dBundle::default());
+fn add_gameplay_plugins(plugins: PluginGroupBuilder) -> PluginGroupBuilder {
+    plugins
+        .add(rng::RngPlugin::default())
+        .add(input::InputMapPlugin::default())
+        .add(map::MapPlugin::default())
+        .add(obstacle::ObstaclePlugin)
+        .add(hex_pos::PositionPlugin)
+        .add(unit::UnitPlugin)
+        .add(schedule::SchedulePlugin)
+        .add(cursor::CursorPlugin)
+        .add(movement::MovementPlugin)
+        .add(path_preview::PathPreviewPlugin)
+        .add(history::HistoryPlugin)
+        .add(save::SavePlugin)
+        .add(turn::TurnPlugin::default())
+        .add(combat::CombatPlugin)
+        .add(replay::ReplayPlugin::default())
+        .add(game_log::GameLogPlugin::default())
+}
+
+/// Game rules only, meshes and UI come from `AssetLoaderPlugin` and `EguiPlugin` when they are added.
+pub struct GameplayPlugins;
+
+impl PluginGroup for GameplayPlugins {
+    fn build(self) -> PluginGroupBuilder {
+        add_gameplay_plugins(PluginGroupBuilder::start::<Self>())
+    }
+}
+
+/// `MinimalPlugins` plus the gameplay, no window nor GPU needed, for simulations and tests.
+pub struct HeadlessPlugins;
+
+impl PluginGroup for HeadlessPlugins {
+    fn build(self) -> PluginGroupBuilder {
+        let plugins = PluginGroupBuilder::start::<Self>()
+            .add(bevy::core::TaskPoolPlugin::default())
+            .add(bevy::core::TypeRegistrationPlugin)
+            .add(bevy::core::FrameCountPlugin)
+            .add(bevy::time::TimePlugin)
+            .add(bevy::app::ScheduleRunnerPlugin::default())
+            .add(bevy::transform::TransformPlugin)
+            .add(bevy::hierarchy::HierarchyPlugin)
+            .add(bevy::input::InputPlugin)
+            // registers the window events the cursor listens to, without opening one
+            .add(WindowPlugin {
+                primary_window: None,
+                exit_condition: ExitCondition::DontExit,
+                close_when_requested: false,
+            })
+            .add(AssetPlugin::default());
+
+        // local rebinds must not change how simulations and tests play out,
+        // and tests must not leave a replay behind
+        add_gameplay_plugins(plugins)
+            .set(input::InputMapPlugin {
+                settings_path: None,
+            })
+            .set(replay::ReplayPlugin {
+                playback: None,
+                path: None,
+            })
+    }
 }
 
+// debug inspector, registered by uncommenting it in `main`
+#[allow(dead_code)]
 fn inspector_ui(world: &mut World) {
     let Ok(egui_context) = world
         .query_filtered::<&mut EguiContext, With<PrimaryWindow>>()
@@ -101,6 +231,8 @@ const WINDOW: WindowSize = WindowSize {
 const HEX_RADIUS: f32 = 30.0;
 const UNIT_RADIUS: f32 = 20.0;
 const SQUART3: f32 = 1.7320508;
+// offset applied to line endpoints so that lines running along hex edges round consistently
+const LINE_NUDGE: Vec3 = Vec3::new(1e-4, 1e-4, -2e-4);
 
 const AXIAL_TO_PIXEL_MAT: Mat3 =
     Mat3::from_cols_array(&[SQUART3, 0., 0., SQUART3 / 2., 3. / 2., 0., 0., 0., 0.]);
@@ -135,7 +267,37 @@ impl HexDistance {
     }
 }
 
-#[derive(Clone)]
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Copy)]
+pub enum HexAxis {
+    Q,
+    R,
+    S,
+}
+
+/// Layouts of offset coordinates, telling which rows (r) or columns (q) are shoved by half a hex.
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Copy)]
+pub enum OffsetLayout {
+    OddR,
+    EvenR,
+    OddQ,
+    EvenQ,
+}
+
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Copy)]
+pub struct Offset {
+    pub col: isize,
+    pub row: isize,
+}
+
+/// Doubled coordinates, where either `col` (doubled width) or `row` (doubled height) moves
+/// by 2 between neighbors.
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Copy)]
+pub enum Doubled {
+    Width { col: isize, row: isize },
+    Height { col: isize, row: isize },
+}
+
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Copy)]
 pub struct Cube {
     q: isize,
     r: isize,
@@ -143,8 +305,6 @@ pub struct Cube {
 }
 
 impl Cube {
-    const ZERO: Self = Self { q: 0, r: 0, s: 0 };
-
     pub fn new(q: isize, r: isize, s: isize) -> Self {
         Self { q, r, s }
     }
@@ -180,9 +340,64 @@ impl Cube {
             s: s as isize,
         }
     }
+
+    /// 60° turn around the origin, east becomes north-east.
+    pub fn rotate_counter_clockwise(&self) -> Self {
+        Self::new(-self.r, -self.s, -self.q)
+    }
+
+    /// 60° turn around the origin, east becomes south-east.
+    pub fn rotate_clockwise(&self) -> Self {
+        Self::new(-self.s, -self.q, -self.r)
+    }
+
+    /// Mirror image across the line going through the origin where `axis` stays constant.
+    pub fn reflect(&self, axis: HexAxis) -> Self {
+        match axis {
+            HexAxis::Q => Self::new(self.q, self.s, self.r),
+            HexAxis::R => Self::new(self.s, self.r, self.q),
+            HexAxis::S => Self::new(self.r, self.q, self.s),
+        }
+    }
+}
+
+/// The six neighbors of a hex, counter-clockwise from east. Hexes are pointy-top and r grows
+/// upwards on screen.
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Copy, Reflect)]
+pub enum HexDirection {
+    East,
+    NorthEast,
+    NorthWest,
+    West,
+    SouthWest,
+    SouthEast,
+}
+
+impl HexDirection {
+    pub const ALL: [Self; 6] = [
+        Self::East,
+        Self::NorthEast,
+        Self::NorthWest,
+        Self::West,
+        Self::SouthWest,
+        Self::SouthEast,
+    ];
+
+    pub fn to_axial(&self) -> Axial {
+        Axial::DIRECTIONS[*self as usize]
+    }
+
+    /// Offset to the diagonal hex lying between this direction and the next counter-clockwise.
+    pub fn diagonal(&self) -> Axial {
+        Axial::DIAGONALS[*self as usize]
+    }
+
+    pub fn rotate_counter_clockwise(&self, steps: usize) -> Self {
+        Self::ALL[(*self as usize + steps) % 6]
+    }
 }
 
-#[derive(Debug, Hash, Eq, PartialEq, Clone, Copy, Default, Reflect)]
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Copy, Default, Reflect, Serialize, Deserialize)]
 pub struct Axial {
     q: isize,
     r: isize,
@@ -191,6 +406,26 @@ pub struct Axial {
 impl Axial {
     const ZERO: Self = Self { q: 0, r: 0 };
 
+    /// Neighbor offsets, indexed by `HexDirection`.
+    pub const DIRECTIONS: [Self; 6] = [
+        Self { q: 1, r: 0 },
+        Self { q: 0, r: 1 },
+        Self { q: -1, r: 1 },
+        Self { q: -1, r: 0 },
+        Self { q: 0, r: -1 },
+        Self { q: 1, r: -1 },
+    ];
+
+    /// Diagonal offsets, each one between two consecutive `DIRECTIONS`.
+    pub const DIAGONALS: [Self; 6] = [
+        Self { q: 1, r: 1 },
+        Self { q: -1, r: 2 },
+        Self { q: -2, r: 1 },
+        Self { q: -1, r: -1 },
+        Self { q: 1, r: -2 },
+        Self { q: 2, r: -1 },
+    ];
+
     pub fn new(q: isize, r: isize) -> Self {
         Self { q, r }
     }
@@ -209,6 +444,114 @@ impl Axial {
         }
     }
 
+    /// Every hex within `q_range` and `r_range`, a rhombus leaning along the r axis.
+    pub fn parallelogram(
+        q_range: RangeInclusive<isize>,
+        r_range: RangeInclusive<isize>,
+    ) -> Vec<Self> {
+        q_range
+            .flat_map(|q| r_range.clone().map(move |r| Self::new(q, r)))
+            .collect()
+    }
+
+    /// Hexagon of hexes at most `radius` steps away from the origin.
+    pub fn hexagon(radius: usize) -> Vec<Self> {
+        Self::ZERO.range(radius).collect()
+    }
+
+    /// Triangle with `size + 1` hexes along each edge, with a corner on the origin.
+    pub fn triangle(size: usize) -> Vec<Self> {
+        let n = size as isize;
+
+        (0..=n)
+            .flat_map(|q| (0..=n - q).map(move |r| Self::new(q, r)))
+            .collect()
+    }
+
+    /// Screen aligned `width` x `height` block of rows (odd-r offset layout) centered on the
+    /// origin, the shape that fills a window best.
+    pub fn rectangle(width: usize, height: usize) -> Vec<Self> {
+        let (width, height) = (width as isize, height as isize);
+        let bottom = -height / 2;
+        let left = -width / 2;
+
+        (bottom..bottom + height)
+            .flat_map(|row| {
+                (left..left + width)
+                    .map(move |col| Self::from_offset(Offset { col, row }, OffsetLayout::OddR))
+            })
+            .collect()
+    }
+
+    /// Turns `self` around `center` by `steps` of 60°, counter-clockwise when positive.
+    pub fn rotate_around(&self, center: &Self, steps: isize) -> Self {
+        let mut cube = Cube::from_axial(*self - *center);
+
+        for _ in 0..steps.rem_euclid(6) {
+            cube = cube.rotate_counter_clockwise();
+        }
+
+        Self::from_cube(cube) + *center
+    }
+
+    /// Mirrors `self` across the line going through `center` along which `axis` is constant.
+    pub fn reflect_around(&self, center: &Self, axis: HexAxis) -> Self {
+        Self::from_cube(Cube::from_axial(*self - *center).reflect(axis)) + *center
+    }
+
+    pub fn to_offset(&self, layout: OffsetLayout) -> Offset {
+        match layout {
+            OffsetLayout::OddR => Offset {
+                col: self.q + (self.r - (self.r & 1)) / 2,
+                row: self.r,
+            },
+            OffsetLayout::EvenR => Offset {
+                col: self.q + (self.r + (self.r & 1)) / 2,
+                row: self.r,
+            },
+            OffsetLayout::OddQ => Offset {
+                col: self.q,
+                row: self.r + (self.q - (self.q & 1)) / 2,
+            },
+            OffsetLayout::EvenQ => Offset {
+                col: self.q,
+                row: self.r + (self.q + (self.q & 1)) / 2,
+            },
+        }
+    }
+
+    pub fn from_offset(offset: Offset, layout: OffsetLayout) -> Self {
+        let Offset { col, row } = offset;
+
+        match layout {
+            OffsetLayout::OddR => Self::new(col - (row - (row & 1)) / 2, row),
+            OffsetLayout::EvenR => Self::new(col - (row + (row & 1)) / 2, row),
+            OffsetLayout::OddQ => Self::new(col, row - (col - (col & 1)) / 2),
+            OffsetLayout::EvenQ => Self::new(col, row - (col + (col & 1)) / 2),
+        }
+    }
+
+    pub fn to_doubled_width(&self) -> Doubled {
+        Doubled::Width {
+            col: 2 * self.q + self.r,
+            row: self.r,
+        }
+    }
+
+    pub fn to_doubled_height(&self) -> Doubled {
+        Doubled::Height {
+            col: self.q,
+            row: 2 * self.r + self.q,
+        }
+    }
+
+    pub fn from_doubled(doubled: Doubled) -> Self {
+        match doubled {
+            Doubled::Width { col, row } => Self::new((col - row) / 2, row),
+            Doubled::Height { col, row } => Self::new(col, (row - col) / 2),
+        }
+    }
+
     pub fn distance(&self, other: &Self) -> usize {
         ((self.q - other.q).abs()
             + (self.q + self.r - other.q - other.r).abs()
@@ -224,18 +567,87 @@ impl Axial {
         HEX_RADIUS * AXIAL_TO_PIXEL_MAT * self.to_vec3()
     }
 
-    pub fn get_neighbors(&self, obstacles: &Vec<Self>) -> Vec<Self> {
+    /// Hexes crossed by a straight line from `self` to `other`, both ends included.
+    pub fn line_to(&self, other: &Self) -> Vec<Self> {
+        self.nudged_line_to(other, LINE_NUDGE)
+    }
+
+    fn nudged_line_to(&self, other: &Self, nudge: Vec3) -> Vec<Self> {
+        let steps = self.distance(other);
+        let start = self.to_vec3().axial_to_cube_vec3() + nudge;
+        let end = other.to_vec3().axial_to_cube_vec3() + nudge;
+
+        (0..=steps)
+            .map(|step| {
+                let t = if steps == 0 {
+                    0.
+                } else {
+                    step as f32 / steps as f32
+                };
+                Self::from_cube(Cube::from_cude_not_rounded(start.lerp(end, t)))
+            })
+            .collect()
+    }
+
+    /// Whether no obstacle stands strictly between `self` and `other`. A line straddling the
+    /// edge between two hexes is traced on both sides and only blocked when both sides are.
+    pub fn line_of_sight(&self, other: &Self, obstacles: &[Self]) -> bool {
+        [LINE_NUDGE, -LINE_NUDGE].iter().any(|&nudge| {
+            self.nudged_line_to(other, nudge)
+                .iter()
+                .filter(|hex| *hex != self && *hex != other)
+                .all(|hex| !obstacles.contains(hex))
+        })
+    }
+
+    pub fn scale(&self, factor: isize) -> Self {
+        Self {
+            q: self.q * factor,
+            r: self.r * factor,
+        }
+    }
+
+    pub fn neighbor(&self, direction: HexDirection) -> Self {
+        *self + direction.to_axial()
+    }
+
+    pub fn diagonal_neighbor(&self, direction: HexDirection) -> Self {
+        *self + direction.diagonal()
+    }
+
+    /// Hexes exactly `radius` steps away, counter-clockwise from the south-west corner.
+    pub fn ring(&self, radius: usize) -> impl Iterator<Item = Self> {
+        let start = *self + HexDirection::SouthWest.to_axial().scale(radius as isize);
+        let steps = if radius == 0 { 1 } else { 6 * radius };
+
+        (0..steps).scan(start, move |hex, step| {
+            let current = *hex;
+            *hex = hex.neighbor(HexDirection::ALL[step / radius.max(1)]);
+            Some(current)
+        })
+    }
+
+    /// Rings of growing radius starting with `self`, up to `radius` included.
+    pub fn spiral(&self, radius: usize) -> impl Iterator<Item = Self> {
+        let center = *self;
+
+        (0..=radius).flat_map(move |ring| center.ring(ring))
+    }
+
+    /// Every hex at most `radius` steps away, `self` included, column by column.
+    pub fn range(&self, radius: usize) -> impl Iterator<Item = Self> {
+        let center = *self;
+        let n = radius as isize;
+
+        (-n..=n).flat_map(move |q| {
+            ((-n).max(-q - n)..=n.min(-q + n)).map(move |r| center + Self::new(q, r))
+        })
+    }
+
+    pub fn get_neighbors(&self, obstacles: &[Self]) -> Vec<Self> {
         let mut neighbors: Vec<Self> = Vec::new();
-        let neighbor_hexs = [
-            Self::new(1, 0),
-            Self::new(0, 1),
-            Self::new(-1, 1),
-            Self::new(1, -1),
-            Self::new(-1, 0),
-            Self::new(0, -1),
-        ];
 
-        for tile in neighbor_hexs.iter() {
+        for tile in Self::DIRECTIONS.iter() {
             let neighbor = *tile + *self;
 
             if !obstacles.contains(&neighbor) {
@@ -246,7 +658,7 @@ impl Axial {
         neighbors
     }
 
-    pub fn bfs(&self, obstacles: &Vec<Self>, max_distance: usize) -> Vec<Self> {
+    pub fn bfs(&self, obstacles: &[Self], max_distance: usize) -> Vec<Self> {
         if max_distance == 0 {
             return vec![];
         }
@@ -281,43 +693,121 @@ impl Axial {
         visited
     }
 
-    pub fn new_bfs(&self, obstacles: &Vec<Self>, max_distance: usize) -> Vec<HexDistance> {
-        if max_distance == 0 {
+    /// Dijkstra flood fill returning every hex reachable for at most `max_cost` MP, paired with
+    /// the cheapest cost to enter it. Hexes missing from `costs` are off the map or impassable.
+    pub fn dijkstra(
+        &self,
+        obstacles: &[Self],
+        costs: &HashMap<Self, usize>,
+        max_cost: usize,
+    ) -> Vec<HexDistance> {
+        if max_cost == 0 {
             return vec![];
         }
-        let mut distance: usize = 1;
-        let mut visited: Vec<HexDistance> = vec![HexDistance::new(*self, 0)];
-        let mut queue: Vec<HexDistance> = vec![HexDistance::new(*self, 0)];
+        let mut cost_so_far: HashMap<Self, usize> = HashMap::from([(*self, 0)]);
+        let mut frontier: BinaryHeap<PathNode> = BinaryHeap::new();
+        frontier.push(PathNode::new(*self, 0, 0));
 
-        while !queue.is_empty() {
-            let mut next_queue: Vec<HexDistance> = Vec::new();
+        while let Some(PathNode { hex, cost, .. }) = frontier.pop() {
+            if cost > cost_so_far[&hex] {
+                continue;
+            }
 
-            for HexDistance { hex, distance: _ } in queue.iter() {
-                let neighbors = hex.get_neighbors(obstacles);
+            for neighbor in hex.get_neighbors(obstacles) {
+                let Some(step_cost) = costs.get(&neighbor) else {
+                    continue;
+                };
+                let new_cost = cost + step_cost;
 
-                for neighbor in neighbors.iter() {
-                    if !visited.contains(&HexDistance::new(*neighbor, distance)) {
-                        visited.push(HexDistance::new(*neighbor, distance));
-                        next_queue.push(HexDistance::new(*neighbor, distance));
-                    }
+                if new_cost > max_cost {
+                    continue;
+                }
+
+                if cost_so_far
+                    .get(&neighbor)
+                    .is_none_or(|&known| new_cost < known)
+                {
+                    cost_so_far.insert(neighbor, new_cost);
+                    frontier.push(PathNode::new(neighbor, new_cost, new_cost));
                 }
             }
+        }
 
-            queue = next_queue;
-            distance += 1;
+        cost_so_far
+            .into_iter()
+            .filter(|(hex, _)| hex != self)
+            .map(|(hex, cost)| HexDistance::new(hex, cost))
+            .collect()
+    }
 
-            if distance == max_distance + 1 {
-                break;
-            }
+    pub fn find_path(
+        &self,
+        goal: &Self,
+        obstacles: &[Self],
+        costs: &HashMap<Self, usize>,
+        max_cost: usize,
+    ) -> Option<Vec<Self>> {
+        // every terrain costs at least 1 MP, so the hex distance never overestimates
+        if obstacles.contains(goal) || self.distance(goal) > max_cost {
+            return None;
         }
 
-        visited.retain(|&x| x != HexDistance::new(*self, 0));
+        let mut came_from: HashMap<Self, Self> = HashMap::new();
+        let mut cost_so_far: HashMap<Self, usize> = HashMap::from([(*self, 0)]);
+        let mut frontier: BinaryHeap<PathNode> = BinaryHeap::new();
+        frontier.push(PathNode::new(*self, 0, self.distance(goal)));
 
-        visited
+        while let Some(PathNode { hex, cost, .. }) = frontier.pop() {
+            if hex == *goal {
+                let mut path = vec![hex];
+                let mut current = hex;
+
+                while let Some(previous) = came_from.get(&current) {
+                    path.push(*previous);
+                    current = *previous;
+                }
+
+                path.reverse();
+                return Some(path);
+            }
+
+            // stale entry, a cheaper route to this hex was already expanded
+            if cost > cost_so_far[&hex] {
+                continue;
+            }
+
+            for neighbor in hex.get_neighbors(obstacles) {
+                let Some(step_cost) = costs.get(&neighbor) else {
+                    continue;
+                };
+                let new_cost = cost + step_cost;
+
+                if new_cost > max_cost {
+                    continue;
+                }
+
+                if cost_so_far
+                    .get(&neighbor)
+                    .is_none_or(|&known| new_cost < known)
+                {
+                    cost_so_far.insert(neighbor, new_cost);
+                    came_from.insert(neighbor, hex);
+                    frontier.push(PathNode::new(
+                        neighbor,
+                        new_cost,
+                        new_cost + neighbor.distance(goal),
+                    ));
+                }
+            }
+        }
+
+        None
     }
+}
 
-    pub fn to_string(&self) -> String {
-        format!("({},{})", self.q, self.r)
+impl fmt::Display for Axial {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({},{})", self.q, self.r)
     }
 }
 
@@ -331,3 +821,414 @@ impl Add for Axial {
         }
     }
 }
+
+impl Sub for Axial {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self {
+            q: self.q - other.q,
+            r: self.r - other.r,
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+struct PathNode {
+    hex: Axial,
+    cost: usize,
+    estimate: usize,
+}
+
+impl PathNode {
+    fn new(hex: Axial, cost: usize, estimate: usize) -> Self {
+        Self {
+            hex,
+            cost,
+            estimate,
+        }
+    }
+}
+
+// reversed so that `BinaryHeap` pops the lowest estimate first, ties go to the node furthest along
+impl Ord for PathNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .estimate
+            .cmp(&self.estimate)
+            .then_with(|| self.cost.cmp(&other.cost))
+    }
+}
+
+impl PartialOrd for PathNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::map::Terrain;
+
+    #[test]
+    fn rotation_steps_follow_directions() {
+        for direction in HexDirection::ALL {
+            for steps in 0..6 {
+                assert_eq!(
+                    direction
+                        .to_axial()
+                        .rotate_around(&Axial::ZERO, steps as isize),
+                    direction.rotate_counter_clockwise(steps).to_axial()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rotation_around_center() {
+        let center = Axial::new(2, -3);
+        let hex = Axial::new(4, -2);
+
+        assert_eq!(hex.rotate_around(&center, 6), hex);
+        assert_eq!(
+            hex.rotate_around(&center, -1),
+            hex.rotate_around(&center, 5)
+        );
+        assert_eq!(
+            hex.rotate_around(&center, 1).rotate_around(&center, -1),
+            hex
+        );
+        assert_eq!(
+            hex.rotate_around(&center, 2).distance(&center),
+            hex.distance(&center)
+        );
+        assert_eq!(center.rotate_around(&center, 3), center);
+    }
+
+    #[test]
+    fn cube_rotations_are_inverse() {
+        let cube = Cube::new(3, -1, -2);
+
+        assert_eq!(cube.rotate_clockwise().rotate_counter_clockwise(), cube);
+        assert_eq!(Cube::new(1, 0, -1).rotate_clockwise(), Cube::new(1, -1, 0));
+    }
+
+    #[test]
+    fn reflection_keeps_axis_and_is_involution() {
+        let center = Axial::new(-1, 2);
+        let hex = Axial::new(3, -2);
+
+        for axis in [HexAxis::Q, HexAxis::R, HexAxis::S] {
+            let reflected = hex.reflect_around(&center, axis);
+
+            assert_eq!(reflected.reflect_around(&center, axis), hex);
+            assert_eq!(reflected.distance(&center), hex.distance(&center));
+        }
+
+        assert_eq!(Cube::new(2, -3, 1).reflect(HexAxis::Q), Cube::new(2, 1, -3));
+        assert_eq!(Cube::new(2, -3, 1).reflect(HexAxis::R), Cube::new(1, -3, 2));
+        assert_eq!(Cube::new(2, -3, 1).reflect(HexAxis::S), Cube::new(-3, 2, 1));
+    }
+
+    #[test]
+    fn offset_round_trip() {
+        let layouts = [
+            OffsetLayout::OddR,
+            OffsetLayout::EvenR,
+            OffsetLayout::OddQ,
+            OffsetLayout::EvenQ,
+        ];
+
+        for layout in layouts {
+            for hex in Axial::ZERO.range(4) {
+                assert_eq!(Axial::from_offset(hex.to_offset(layout), layout), hex);
+            }
+        }
+    }
+
+    #[test]
+    fn offset_known_values() {
+        let hex = Axial::new(-2, 3);
+
+        assert_eq!(
+            hex.to_offset(OffsetLayout::OddR),
+            Offset { col: -1, row: 3 }
+        );
+        assert_eq!(
+            hex.to_offset(OffsetLayout::EvenR),
+            Offset { col: 0, row: 3 }
+        );
+        assert_eq!(
+            hex.to_offset(OffsetLayout::OddQ),
+            Offset { col: -2, row: 2 }
+        );
+        assert_eq!(
+            hex.to_offset(OffsetLayout::EvenQ),
+            Offset { col: -2, row: 2 }
+        );
+        assert_eq!(
+            Axial::new(-1, 0).to_offset(OffsetLayout::OddQ),
+            Offset { col: -1, row: -1 }
+        );
+        assert_eq!(
+            Axial::new(-1, 0).to_offset(OffsetLayout::EvenQ),
+            Offset { col: -1, row: 0 }
+        );
+    }
+
+    #[test]
+    fn doubled_round_trip() {
+        for hex in Axial::ZERO.range(4) {
+            assert_eq!(Axial::from_doubled(hex.to_doubled_width()), hex);
+            assert_eq!(Axial::from_doubled(hex.to_doubled_height()), hex);
+        }
+
+        assert_eq!(
+            Axial::new(1, -1).to_doubled_width(),
+            Doubled::Width { col: 1, row: -1 }
+        );
+        assert_eq!(
+            Axial::new(1, -1).to_doubled_height(),
+            Doubled::Height { col: 1, row: -1 }
+        );
+    }
+
+    #[test]
+    fn rectangle_rows_are_aligned() {
+        let hexes = Axial::rectangle(5, 4);
+
+        assert_eq!(hexes.len(), 20);
+
+        for hex in hexes {
+            let Offset { col, .. } = hex.to_offset(OffsetLayout::OddR);
+            assert!((-2..3).contains(&col));
+        }
+    }
+
+    fn flat_costs(radius: usize) -> HashMap<Axial, usize> {
+        Axial::ZERO.range(radius).map(|hex| (hex, 1)).collect()
+    }
+
+    fn assert_connected(path: &[Axial]) {
+        for step in path.windows(2) {
+            assert_eq!(step[0].distance(&step[1]), 1, "{:?} is not a step", step);
+        }
+    }
+
+    #[test]
+    fn path_to_itself() {
+        assert_eq!(
+            Axial::ZERO.find_path(&Axial::ZERO, &[], &flat_costs(2), 0),
+            Some(vec![Axial::ZERO])
+        );
+    }
+
+    #[test]
+    fn path_goes_around_a_wall() {
+        let wall = (-3..=2).map(|r| Axial::new(1, r)).collect::<Vec<Axial>>();
+        let goal = Axial::new(2, 0);
+
+        let path = Axial::ZERO
+            .find_path(&goal, &wall, &flat_costs(4), 20)
+            .unwrap();
+
+        assert_eq!(path.first(), Some(&Axial::ZERO));
+        assert_eq!(path.last(), Some(&goal));
+        assert!(path.len() - 1 > Axial::ZERO.distance(&goal));
+        assert!(path.iter().all(|hex| !wall.contains(hex)));
+        assert_connected(&path);
+    }
+
+    #[test]
+    fn unreachable_goal_has_no_path() {
+        let goal = Axial::new(2, 0);
+        let walled_in = goal.ring(1).collect::<Vec<Axial>>();
+
+        assert_eq!(
+            Axial::ZERO.find_path(&goal, &walled_in, &flat_costs(4), 20),
+            None
+        );
+        assert_eq!(
+            Axial::ZERO.find_path(&goal, &[goal], &flat_costs(4), 20),
+            None
+        );
+        assert_eq!(
+            Axial::ZERO.find_path(&Axial::new(9, 0), &[], &flat_costs(4), 20),
+            None
+        );
+    }
+
+    #[test]
+    fn path_stops_at_max_cost() {
+        let wall = (-3..=2).map(|r| Axial::new(1, r)).collect::<Vec<Axial>>();
+        let goal = Axial::new(2, 0);
+        let cost = Axial::ZERO
+            .find_path(&goal, &wall, &flat_costs(4), 20)
+            .unwrap()
+            .len()
+            - 1;
+
+        assert!(Axial::ZERO
+            .find_path(&goal, &wall, &flat_costs(4), cost)
+            .is_some());
+        assert_eq!(
+            Axial::ZERO.find_path(&goal, &wall, &flat_costs(4), cost - 1),
+            None
+        );
+    }
+
+    #[test]
+    fn straight_path_is_connected() {
+        let goal = Axial::new(3, -4);
+        let path = Axial::ZERO
+            .find_path(&goal, &[], &flat_costs(5), 10)
+            .unwrap();
+
+        assert_eq!(path.len(), Axial::ZERO.distance(&goal) + 1);
+        assert_connected(&path);
+    }
+
+    fn terrain_costs(terrain: &[(Axial, Terrain)]) -> HashMap<Axial, usize> {
+        terrain
+            .iter()
+            .filter_map(|(hex, terrain)| terrain.cost().map(|cost| (*hex, cost)))
+            .collect()
+    }
+
+    fn range_costs(range: &[HexDistance]) -> HashMap<Axial, usize> {
+        range.iter().map(|hex| (hex.hex, hex.distance)).collect()
+    }
+
+    #[test]
+    fn range_sums_terrain_costs() {
+        let costs = terrain_costs(&[
+            (Axial::ZERO, Terrain::Plains),
+            (Axial::new(1, 0), Terrain::Forest),
+            (Axial::new(2, 0), Terrain::Swamp),
+            (Axial::new(3, 0), Terrain::Plains),
+        ]);
+
+        let range = range_costs(&Axial::ZERO.dijkstra(&[], &costs, 10));
+
+        assert_eq!(
+            range,
+            HashMap::from([
+                (Axial::new(1, 0), 2),
+                (Axial::new(2, 0), 5),
+                (Axial::new(3, 0), 6),
+            ])
+        );
+    }
+
+    #[test]
+    fn water_is_impassable() {
+        let costs = terrain_costs(&[
+            (Axial::ZERO, Terrain::Plains),
+            (Axial::new(1, 0), Terrain::Water),
+            (Axial::new(2, 0), Terrain::Plains),
+        ]);
+
+        assert!(Axial::ZERO.dijkstra(&[], &costs, 10).is_empty());
+    }
+
+    #[test]
+    fn range_includes_the_max_cost_boundary() {
+        let range = range_costs(&Axial::ZERO.dijkstra(&[], &flat_costs(4), 2));
+
+        assert_eq!(range.get(&Axial::new(2, 0)), Some(&2));
+        assert_eq!(range.get(&Axial::new(3, 0)), None);
+        assert_eq!(range.len(), 18);
+        assert!(!range.contains_key(&Axial::ZERO));
+    }
+
+    #[test]
+    fn line_spans_the_distance() {
+        let start = Axial::new(-2, 1);
+
+        for end in [
+            Axial::new(3, -1),
+            Axial::new(-2, -3),
+            Axial::new(1, 2),
+            start,
+        ] {
+            let line = start.line_to(&end);
+
+            assert_eq!(line.len(), start.distance(&end) + 1);
+            assert_eq!(line.first(), Some(&start));
+            assert_eq!(line.last(), Some(&end));
+            assert_connected(&line);
+        }
+    }
+
+    #[test]
+    fn diagonal_line_straddles_an_edge() {
+        let end = HexDirection::East.diagonal();
+        let sides = [Axial::new(1, 0), Axial::new(0, 1)];
+
+        let line = Axial::ZERO.line_to(&end);
+        assert_eq!(line.len(), 3);
+        assert!(sides.contains(&line[1]));
+
+        assert!(Axial::ZERO.line_of_sight(&end, &[sides[0]]));
+        assert!(Axial::ZERO.line_of_sight(&end, &[sides[1]]));
+        assert!(!Axial::ZERO.line_of_sight(&end, &sides));
+    }
+
+    #[test]
+    fn sight_is_blocked_between_the_ends_only() {
+        let target = Axial::new(3, 0);
+
+        assert!(Axial::ZERO.line_of_sight(&target, &[]));
+        assert!(!Axial::ZERO.line_of_sight(&target, &[Axial::new(2, 0)]));
+        assert!(Axial::ZERO.line_of_sight(&target, &[target]));
+        assert!(Axial::ZERO.line_of_sight(&target, &[Axial::new(1, 1)]));
+    }
+
+    #[test]
+    fn ring_sizes() {
+        let center = Axial::new(1, -2);
+
+        assert_eq!(center.ring(0).collect::<Vec<Axial>>(), vec![center]);
+
+        for radius in 1..5 {
+            let ring = center.ring(radius).collect::<HashSet<Axial>>();
+
+            assert_eq!(ring.len(), 6 * radius);
+            assert!(ring.iter().all(|hex| hex.distance(&center) == radius));
+        }
+    }
+
+    #[test]
+    fn spiral_and_range_cover_the_same_hexes() {
+        let center = Axial::new(-1, 3);
+
+        for radius in 0..5 {
+            let spiral = center.spiral(radius).collect::<Vec<Axial>>();
+            let range = center.range(radius).collect::<HashSet<Axial>>();
+
+            assert_eq!(spiral.len(), 1 + 3 * radius * (radius + 1));
+            assert_eq!(range.len(), spiral.len());
+            assert_eq!(spiral.iter().copied().collect::<HashSet<Axial>>(), range);
+            // ring by ring, from the center outwards
+            assert!(spiral
+                .windows(2)
+                .all(|pair| pair[0].distance(&center) <= pair[1].distance(&center)));
+        }
+    }
+
+    #[test]
+    fn directions_follow_the_neighbor_order() {
+        let hex = Axial::new(2, -1);
+
+        assert_eq!(
+            HexDirection::ALL
+                .iter()
+                .map(|&direction| hex.neighbor(direction))
+                .collect::<Vec<Axial>>(),
+            hex.get_neighbors(&[])
+        );
+    }
+}
@@ -1,58 +1,546 @@
+use std::collections::HashMap;
+
 use bevy::{
+    app::AppExit,
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext, LoadState},
     prelude::*,
     sprite::{MaterialMesh2dBundle, Mesh2dHandle},
+    utils::BoxedFuture,
 };
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-use crate::{asset_loader::GameAssets, Axial};
+use crate::{
+    asset_loader::GameAssets,
+    schedule::{GameState, InGameSet},
+    Axial,
+};
 use crate::{hex_pos::HexPos, Vec3Extra};
 
+/// Map played when none is given, and the fallback when the requested one fails to load.
+pub const DEFAULT_MAP_PATH: &str = "maps/default.map.ron";
+
 #[derive(Component)]
 pub struct Hex;
 
-pub struct MapPlugin;
+#[derive(
+    Component, Reflect, Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize,
+)]
+#[reflect(Component)]
+pub enum Terrain {
+    #[default]
+    Plains,
+    Forest,
+    Swamp,
+    Water,
+    Road,
+}
+
+impl Terrain {
+    /// MP needed to enter a hex of this terrain, `None` when it cannot be entered at all.
+    pub fn cost(&self) -> Option<usize> {
+        match self {
+            Terrain::Road => Some(1),
+            Terrain::Plains => Some(1),
+            Terrain::Forest => Some(2),
+            Terrain::Swamp => Some(3),
+            Terrain::Water => None,
+        }
+    }
+
+    pub fn material(&self, game_assets: &GameAssets) -> Handle<ColorMaterial> {
+        match self {
+            Terrain::Plains => game_assets.white.clone(),
+            Terrain::Forest => game_assets.forest_color.clone(),
+            Terrain::Swamp => game_assets.swamp_color.clone(),
+            Terrain::Water => game_assets.water_color.clone(),
+            Terrain::Road => game_assets.road_color.clone(),
+        }
+    }
+}
+
+/// Entry cost of every enterable hex, as taken by `Axial::dijkstra` and `Axial::find_path`.
+pub fn terrain_costs<'a>(
+    terrain: impl IntoIterator<Item = (&'a HexPos, &'a Terrain)>,
+) -> HashMap<Axial, usize> {
+    terrain
+        .into_iter()
+        .filter_map(|(pos, terrain)| terrain.cost().map(|cost| (pos.pos, cost)))
+        .collect()
+}
+
+/// Grid layout of a map, centered on or anchored at the origin.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum MapShape {
+    /// Inclusive axial bounds.
+    Parallelogram {
+        q: (isize, isize),
+        r: (isize, isize),
+    },
+    Hexagon {
+        radius: usize,
+    },
+    /// Rows of hexes, `width` columns by `height` rows on screen.
+    Rectangle {
+        width: usize,
+        height: usize,
+    },
+    Triangle {
+        size: usize,
+    },
+}
+
+impl MapShape {
+    pub fn hexes(&self) -> Vec<Axial> {
+        match self {
+            MapShape::Parallelogram { q, r } => Axial::parallelogram(q.0..=q.1, r.0..=r.1),
+            MapShape::Hexagon { radius } => Axial::hexagon(*radius),
+            MapShape::Rectangle { width, height } => Axial::rectangle(*width, *height),
+            MapShape::Triangle { size } => Axial::triangle(*size),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnitSpawn {
+    /// Filled with "Unit <n>" by the map loader when left out, `n` counting from 1 in file order.
+    #[serde(default)]
+    pub name: Option<String>,
+    pub pos: Axial,
+    pub mp: usize,
+    #[serde(default)]
+    pub team: usize,
+    #[serde(default)]
+    pub initiative: usize,
+    #[serde(default = "UnitSpawn::default_hp")]
+    pub hp: usize,
+    #[serde(default = "UnitSpawn::default_damage")]
+    pub damage: usize,
+    #[serde(default = "UnitSpawn::default_range")]
+    pub range: usize,
+    #[serde(default)]
+    pub defense: usize,
+}
+
+impl UnitSpawn {
+    fn default_hp() -> usize {
+        10
+    }
+
+    fn default_damage() -> usize {
+        4
+    }
+
+    fn default_range() -> usize {
+        1
+    }
+}
+
+/// Map description loaded from a `.map.ron` file.
+#[derive(Asset, TypePath, Debug, Clone, Deserialize)]
+pub struct MapData {
+    pub shape: MapShape,
+    #[serde(default)]
+    pub default_terrain: Terrain,
+    /// Hexes whose terrain differs from `default_terrain`.
+    #[serde(default)]
+    pub terrain: HashMap<Axial, Terrain>,
+    #[serde(default)]
+    pub obstacles: Vec<Axial>,
+    #[serde(default)]
+    pub units: Vec<UnitSpawn>,
+}
+
+impl MapData {
+    pub fn hexes(&self) -> Vec<(Axial, Terrain)> {
+        self.shape
+            .hexes()
+            .into_iter()
+            .map(|hex| (hex, self.terrain_at(&hex)))
+            .collect()
+    }
+
+    /// Swaps the grid layout, dropping terrain, obstacles and units that no longer fit on it.
+    /// The map is left as it was when the reshaped one does not validate.
+    pub fn reshape(&mut self, shape: MapShape) -> Result<(), MapLoadError> {
+        let hexes = shape.hexes();
+        let mut reshaped = self.clone();
+
+        reshaped.shape = shape;
+        reshaped.terrain.retain(|pos, _| hexes.contains(pos));
+        reshaped.obstacles.retain(|pos| hexes.contains(pos));
+        reshaped.units.retain(|unit| hexes.contains(&unit.pos));
+        reshaped.validate()?;
+
+        for pos in self.obstacles.iter().filter(|pos| !hexes.contains(pos)) {
+            warn!(
+                "obstacle at ({}, {}) is outside the new map shape, dropped",
+                pos.q, pos.r
+            );
+        }
+        for unit in self.units.iter().filter(|unit| !hexes.contains(&unit.pos)) {
+            warn!(
+                "{} at ({}, {}) is outside the new map shape, dropped",
+                unit.name.as_deref().unwrap_or("Unit"),
+                unit.pos.q,
+                unit.pos.r
+            );
+        }
+
+        *self = reshaped;
+        Ok(())
+    }
+
+    pub fn terrain_at(&self, hex: &Axial) -> Terrain {
+        self.terrain
+            .get(hex)
+            .copied()
+            .unwrap_or(self.default_terrain)
+    }
+
+    fn validate(&self) -> Result<(), MapLoadError> {
+        let hexes = self.shape.hexes();
+
+        if hexes.is_empty() {
+            return Err(MapLoadError::EmptyMap);
+        }
+
+        let out_of_bounds = |kind: &'static str, pos: &Axial| {
+            (!hexes.contains(pos)).then(|| MapLoadError::OutOfBounds {
+                kind,
+                pos: pos.to_string(),
+            })
+        };
+
+        for pos in self.terrain.keys() {
+            if let Some(error) = out_of_bounds("terrain", pos) {
+                return Err(error);
+            }
+        }
+
+        for pos in &self.obstacles {
+            if let Some(error) = out_of_bounds("obstacle", pos) {
+                return Err(error);
+            }
+        }
+
+        for (i, unit) in self.units.iter().enumerate() {
+            if let Some(error) = out_of_bounds("unit", &unit.pos) {
+                return Err(error);
+            }
+
+            if self.obstacles.contains(&unit.pos)
+                || self.terrain_at(&unit.pos).cost().is_none()
+                || self.units[..i].iter().any(|other| other.pos == unit.pos)
+            {
+                return Err(MapLoadError::BlockedSpawn(unit.pos.to_string()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum MapLoadError {
+    #[error("could not read map file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("malformed map file: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    #[error("map shape contains no hexes")]
+    EmptyMap,
+    #[error("{kind} at {pos} is outside the map shape")]
+    OutOfBounds { kind: &'static str, pos: String },
+    #[error("unit spawn at {0} is on an obstacle, impassable terrain or another unit")]
+    BlockedSpawn(String),
+}
+
+#[derive(Default)]
+pub struct MapLoader;
+
+impl AssetLoader for MapLoader {
+    type Asset = MapData;
+    type Settings = ();
+    type Error = MapLoadError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let mut map_data = ron::de::from_bytes::<MapData>(&bytes)?;
+            map_data.validate()?;
+
+            for (index, unit) in map_data.units.iter_mut().enumerate() {
+                unit.name
+                    .get_or_insert_with(|| format!("Unit {}", index + 1));
+            }
+            Ok(map_data)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["map.ron"]
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct CurrentMap {
+    pub path: String,
+    pub shape: Option<MapShape>,
+    pub handle: Handle<MapData>,
+}
+
+pub struct MapPlugin {
+    /// Path of the map file, relative to the `assets` folder.
+    pub path: String,
+    /// Overrides the grid layout described in the map file.
+    pub shape: Option<MapShape>,
+}
+
+impl Default for MapPlugin {
+    fn default() -> Self {
+        Self {
+            path: DEFAULT_MAP_PATH.to_string(),
+            shape: None,
+        }
+    }
+}
 
 impl Plugin for MapPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(PostStartup, generate_map);
+        app.register_type::<Terrain>()
+            .init_asset::<MapData>()
+            .init_asset_loader::<MapLoader>()
+            .insert_resource(CurrentMap {
+                path: self.path.clone(),
+                shape: self.shape.clone(),
+                ..default()
+            })
+            .add_systems(Startup, load_map)
+            .add_systems(Update, wait_for_map.run_if(in_state(GameState::Loading)))
+            .add_systems(
+                OnEnter(GameState::InGame),
+                generate_map.in_set(InGameSet::InitEntities),
+            );
+    }
+}
+
+fn load_map(mut current_map: ResMut<CurrentMap>, asset_server: Res<AssetServer>) {
+    current_map.handle = asset_server.load(current_map.path.clone());
+}
+
+fn wait_for_map(
+    mut current_map: ResMut<CurrentMap>,
+    asset_server: Res<AssetServer>,
+    mut maps: ResMut<Assets<MapData>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut app_exit_event_w: EventWriter<AppExit>,
+) {
+    match asset_server.load_state(&current_map.handle) {
+        LoadState::Loaded => {}
+        // the `MapLoadError` itself is logged by the asset server
+        LoadState::Failed if current_map.path != DEFAULT_MAP_PATH => {
+            error!(
+                "could not load map {}, falling back to {}",
+                current_map.path, DEFAULT_MAP_PATH
+            );
+            current_map.path = DEFAULT_MAP_PATH.to_string();
+            current_map.handle = asset_server.load(DEFAULT_MAP_PATH);
+            return;
+        }
+        LoadState::Failed => {
+            error!("could not load map {}, exiting", current_map.path);
+            app_exit_event_w.send(AppExit);
+            return;
+        }
+        _ => return,
+    }
+
+    if let (Some(shape), Some(map_data)) =
+        (current_map.shape.clone(), maps.get_mut(&current_map.handle))
+    {
+        if let Err(error) = map_data.reshape(shape) {
+            error!(
+                "could not reshape map {}: {}, keeping its own shape",
+                current_map.path, error
+            );
+            // so replays record the shape that is actually played
+            current_map.shape = None;
+        }
+    }
+
+    next_state.set(GameState::InGame);
+}
+
+fn generate_map(
+    mut commands: Commands,
+    game_assets: Option<Res<GameAssets>>,
+    current_map: Res<CurrentMap>,
+    maps: Res<Assets<MapData>>,
+) {
+    let Some(map_data) = maps.get(&current_map.handle) else {
+        return;
+    };
+
+    for (hex, terrain) in map_data.hexes() {
+        spawn_hex(&mut commands, game_assets.as_deref(), hex, terrain);
     }
 }
 
-fn generate_map(mut commands: Commands, game_assets: Res<GameAssets>) {
+/// Spawns a map tile, along with its coordinates label when rendering.
+pub fn spawn_hex(
+    commands: &mut Commands,
+    game_assets: Option<&GameAssets>,
+    hex: Axial,
+    terrain: Terrain,
+) -> Entity {
+    let transform = Transform {
+        translation: hex.to_vec3_pixel(),
+        ..default()
+    };
+    let mut entity = commands.spawn((Hex, terrain, HexPos { pos: hex }, Name::new("Hex")));
+
+    let Some(game_assets) = game_assets else {
+        return entity
+            .insert(TransformBundle::from_transform(transform))
+            .id();
+    };
+
     let text_style = TextStyle {
         font: game_assets.font.clone(),
         font_size: 12.0,
         color: Color::BLACK,
     };
 
-    for i in -5..6 {
-        for j in -6..7 {
-            let hex = Axial::new(i, j);
-            commands.spawn((
+    entity
+        .insert(MaterialMesh2dBundle {
+            mesh: Mesh2dHandle(game_assets.hexagone.clone()),
+            material: terrain.material(game_assets),
+            transform,
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn((
                 Text2dBundle {
-                    text: Text::from_section(hex.to_string(), text_style.clone())
+                    text: Text::from_section(hex.to_string(), text_style)
                         .with_justify(JustifyText::Center),
                     transform: Transform {
-                        translation: hex.clone().to_vec3_pixel().set_z(10.),
+                        translation: Vec3::ZERO.set_z(10.),
                         ..default()
                     },
                     ..default()
                 },
                 Name::new("HexText"),
             ));
-            commands.spawn((
-                MaterialMesh2dBundle {
-                    mesh: Mesh2dHandle(game_assets.hexagone.clone()),
-                    material: game_assets.white.clone(),
-                    transform: Transform {
-                        translation: hex.to_vec3_pixel(),
-                        ..default()
-                    },
-                    ..default()
-                },
-                Hex,
-                HexPos { pos: hex },
-                Name::new("Hex"),
-            ));
+        })
+        .id()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::{
+        map::{CurrentMap, MapData, MapShape, DEFAULT_MAP_PATH},
+        test_harness::TestApp,
+        Axial,
+    };
+
+    fn small_map() -> MapData {
+        ron::de::from_str(
+            "(
+                shape: Hexagon(radius: 2),
+                obstacles: [(q: 0, r: 1), (q: 2, r: 0)],
+                units: [(pos: (q: 0, r: 0), mp: 4), (pos: (q: -2, r: 0), mp: 4)],
+            )",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn missing_map_falls_back_to_the_default() {
+        let game = TestApp::with_map("maps/missing.map.ron");
+
+        assert_eq!(
+            game.app.world.resource::<CurrentMap>().path,
+            DEFAULT_MAP_PATH
+        );
+    }
+
+    #[test]
+    fn reshaping_drops_what_no_longer_fits() {
+        let mut map_data = small_map();
+
+        map_data.reshape(MapShape::Hexagon { radius: 1 }).unwrap();
+
+        assert_eq!(map_data.obstacles, vec![Axial::new(0, 1)]);
+        assert_eq!(map_data.units.len(), 1);
+        assert_eq!(map_data.units[0].pos, Axial::new(0, 0));
+    }
+
+    #[test]
+    fn reshaping_to_an_empty_shape_keeps_the_map() {
+        let mut map_data = small_map();
+
+        assert!(map_data
+            .reshape(MapShape::Rectangle {
+                width: 0,
+                height: 0
+            })
+            .is_err());
+        assert_eq!(map_data.obstacles.len(), 2);
+        assert_eq!(map_data.units.len(), 2);
+    }
+
+    fn assert_unique(hexes: &[Axial]) {
+        let unique = hexes.iter().collect::<HashSet<&Axial>>();
+        assert_eq!(unique.len(), hexes.len());
+    }
+
+    #[test]
+    fn hexagon_shape() {
+        for radius in 0..5 {
+            let hexes = MapShape::Hexagon { radius }.hexes();
+
+            assert_eq!(hexes.len(), 1 + 3 * radius * (radius + 1));
+            assert!(hexes
+                .iter()
+                .all(|hex| hex.distance(&Axial::new(0, 0)) <= radius));
+            assert_unique(&hexes);
         }
     }
+
+    #[test]
+    fn triangle_shape() {
+        for size in 0..5 {
+            let hexes = MapShape::Triangle { size }.hexes();
+            let n = size as isize;
+
+            assert_eq!(hexes.len(), (size + 1) * (size + 2) / 2);
+            assert!(hexes
+                .iter()
+                .all(|hex| hex.q >= 0 && hex.r >= 0 && hex.q + hex.r <= n));
+            assert_unique(&hexes);
+        }
+    }
+
+    #[test]
+    fn parallelogram_shape() {
+        let hexes = MapShape::Parallelogram {
+            q: (-2, 1),
+            r: (0, 4),
+        }
+        .hexes();
+
+        assert_eq!(hexes.len(), 4 * 5);
+        assert!(hexes
+            .iter()
+            .all(|hex| (-2..=1).contains(&hex.q) && (0..=4).contains(&hex.r)));
+        assert_unique(&hexes);
+    }
 }
@@ -5,10 +5,15 @@ use bevy::{
 
 use crate::{
     asset_loader::GameAssets,
+    combat::{Targeting, UnitDiedEvent},
     cursor::CursorGridClickEvent,
     hex_pos::HexPos,
+    history::{CommandHistory, GameCommand},
+    map::{terrain_costs, Hex, Terrain},
     obstacle::Obstacle,
+    replay::{CommandAppliedEvent, ReplayAction},
     schedule::UpdateSet,
+    turn::TurnStartedEvent,
     unit::{CurrentActiveUnit, Unit},
     Axial, HexDistance, Vec3Extra,
 };
@@ -38,122 +43,434 @@ pub struct UnitMovedEvent {
     pub entity: Entity,
 }
 
+/// Asks for `entity` to walk to `to`, sent by grid clicks and replays alike.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct MoveUnitEvent {
+    pub entity: Entity,
+    pub to: Axial,
+}
+
 #[derive(Component)]
 pub struct MovementHex {
+    /// Cheapest MP cost to reach this hex from the selected unit.
+    #[allow(dead_code)]
     pub distance: usize,
 }
 
+/// Unit walking along `path`, `HexPos` is only updated once it reaches the last hex.
+#[derive(Component, Debug)]
+pub struct MovementAnimation {
+    pub path: Vec<Axial>,
+    pub elapsed: f32,
+}
+
+/// Only `EaseInOut` is used by default, the others are there to tune `MovementAnimationSettings`.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    #[default]
+    EaseInOut,
+}
+
+impl Easing {
+    pub fn apply(&self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2. - t),
+            Easing::EaseInOut => t * t * (3. - 2. * t),
+        }
+    }
+}
+
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct MovementAnimationSettings {
+    /// Hexes walked per second.
+    pub speed: f32,
+    /// Applied over the whole path, so the unit does not stop on every hex.
+    pub easing: Easing,
+}
+
+impl Default for MovementAnimationSettings {
+    fn default() -> Self {
+        Self {
+            speed: 6.,
+            easing: Easing::default(),
+        }
+    }
+}
+
+/// Run condition holding back player input while a unit is walking.
+pub fn movement_idle(animation_query: Query<(), With<MovementAnimation>>) -> bool {
+    animation_query.is_empty()
+}
+
 pub struct MovementPlugin;
 
 impl Plugin for MovementPlugin {
     fn build(&self, app: &mut App) {
-        app.add_event::<UnitMovedEvent>().add_systems(
-            Update,
-            (
-                movement_hex_clicked.in_set(UpdateSet::UserInput),
-                recalc_movement_range.in_set(UpdateSet::StateCalc),
-                despawn_update_movement_range.in_set(UpdateSet::DespawnObjects),
-                respawn_movement_range.in_set(UpdateSet::RespawnObjects),
-            ),
-        );
+        app.add_event::<UnitMovedEvent>()
+            .add_event::<MoveUnitEvent>()
+            .init_resource::<MovementAnimationSettings>()
+            .add_systems(
+                Update,
+                (
+                    (
+                        movement_hex_clicked.run_if(resource_equals(Targeting::None)),
+                        move_unit,
+                        animate_movement,
+                    )
+                        .chain()
+                        .in_set(UpdateSet::UserInput),
+                    recalc_movement_range.in_set(UpdateSet::StateCalc),
+                    despawn_update_movement_range.in_set(UpdateSet::DespawnObjects),
+                    respawn_movement_range.in_set(UpdateSet::RespawnObjects),
+                ),
+            );
     }
 }
 
 fn movement_hex_clicked(
     mut cursor_grid_click_event_r: EventReader<CursorGridClickEvent>,
-    mut unit_query: Query<(&mut HexPos, &mut Mp, &mut Transform), With<Unit>>,
-    movement_hex_query: Query<(&HexPos, &MovementHex), Without<Unit>>,
-    selected_unit: ResMut<CurrentActiveUnit>,
-    mut unit_moved_event_w: EventWriter<UnitMovedEvent>,
+    mut move_unit_event_w: EventWriter<MoveUnitEvent>,
+    movement_hex_query: Query<&HexPos, With<MovementHex>>,
+    selected_unit: Res<CurrentActiveUnit>,
 ) {
     for event in cursor_grid_click_event_r.read() {
-        let maybe_hex = movement_hex_query
+        let maybe_hex = movement_hex_query.iter().find(|pos| pos.pos == event.pos);
+
+        if let (Some(hex), Some(entity)) = (maybe_hex, selected_unit.entity) {
+            move_unit_event_w.send(MoveUnitEvent {
+                entity,
+                to: hex.pos,
+            });
+        }
+    }
+}
+
+fn move_unit(
+    mut move_unit_event_r: EventReader<MoveUnitEvent>,
+    mut commands: Commands,
+    mut unit_query: Query<(&HexPos, &mut Mp), With<Unit>>,
+    obstacle_query: Query<&HexPos, (With<Obstacle>, Without<Unit>)>,
+    terrain_query: Query<(&HexPos, &Terrain), (With<Hex>, Without<Unit>)>,
+    mut history: ResMut<CommandHistory>,
+    mut command_applied_event_w: EventWriter<CommandAppliedEvent>,
+) {
+    for event in move_unit_event_r.read() {
+        let obstacles = obstacle_query
             .iter()
-            .find(|(pos, _)| pos.pos == event.pos);
-
-        match maybe_hex {
-            Some((hex, MovementHex { distance })) => match selected_unit.entity {
-                Some(unit_entity) => {
-                    if let Ok((mut unit_hex_pos, mut mp, mut unit_transform)) =
-                        unit_query.get_mut(unit_entity)
-                    {
-                        // let distance = unit_hex_pos.pos.distance(&hex.pos);
-
-                        println!("before mp: {}, distance: {}", mp.current, distance);
-
-                        mp.current -= distance;
-                        unit_hex_pos.pos = hex.pos;
-                        unit_transform.translation = hex.pos.to_vec3_pixel().set_z(2.);
-
-                        println!("after mp: {}, distance: {}", mp.current, distance);
-
-                        unit_moved_event_w.send(UnitMovedEvent {
-                            entity: unit_entity,
-                        });
-                    }
-                }
-                None => {}
-            },
-            None => {}
+            .chain(unit_query.iter().map(|(pos, _)| pos))
+            .map(|pos| pos.pos)
+            .collect::<Vec<Axial>>();
+        let costs = terrain_costs(&terrain_query);
+
+        if let Ok((unit_hex_pos, mut mp)) = unit_query.get_mut(event.entity) {
+            let Some(path) = unit_hex_pos
+                .pos
+                .find_path(&event.to, &obstacles, &costs, mp.current)
+            else {
+                continue;
+            };
+            let distance: usize = path.iter().skip(1).map(|hex| costs[hex]).sum();
+
+            mp.current -= distance;
+            history.push(GameCommand::MoveUnit {
+                entity: event.entity,
+                from: unit_hex_pos.pos,
+                to: event.to,
+                cost: distance,
+            });
+            command_applied_event_w.send(CommandAppliedEvent {
+                action: ReplayAction::Move {
+                    from: unit_hex_pos.pos,
+                    to: event.to,
+                },
+            });
+            commands
+                .entity(event.entity)
+                .insert(MovementAnimation { path, elapsed: 0. });
+        }
+    }
+}
+
+fn animate_movement(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<MovementAnimationSettings>,
+    mut unit_moved_event_w: EventWriter<UnitMovedEvent>,
+    mut animation_query: Query<(Entity, &mut MovementAnimation, &mut HexPos, &mut Transform)>,
+) {
+    for (entity, mut animation, mut hex_pos, mut transform) in animation_query.iter_mut() {
+        let steps = animation.path.len().saturating_sub(1);
+        let duration = steps as f32 / settings.speed;
+        animation.elapsed += time.delta_seconds();
+
+        if steps == 0 || animation.elapsed >= duration {
+            let destination = *animation.path.last().unwrap_or(&hex_pos.pos);
+            hex_pos.pos = destination;
+            transform.translation = destination.to_vec3_pixel().set_z(2.);
+
+            commands.entity(entity).remove::<MovementAnimation>();
+            unit_moved_event_w.send(UnitMovedEvent { entity });
+            continue;
         }
+
+        let progress = settings.easing.apply(animation.elapsed / duration) * steps as f32;
+        let segment = (progress.floor() as usize).min(steps - 1);
+        let from = animation.path[segment].to_vec3_pixel();
+        let to = animation.path[segment + 1].to_vec3_pixel();
+
+        transform.translation = from.lerp(to, progress - segment as f32).set_z(2.);
     }
 }
 
 fn recalc_movement_range(
     mut unit_moved_event_r: EventReader<UnitMovedEvent>,
+    mut turn_started_event_r: EventReader<TurnStartedEvent>,
+    mut unit_died_event_r: EventReader<UnitDiedEvent>,
+    added_unit_query: Query<(), Added<Unit>>,
     obstacle_query: Query<&HexPos, With<Obstacle>>,
+    terrain_query: Query<(&HexPos, &Terrain), With<Hex>>,
     mut movement_query: Query<(&HexPos, &Mp, &mut MovementRange)>,
 ) {
-    for _ in unit_moved_event_r.read() {
-        for (hex_pos, mp, mut movement_range) in movement_query.iter_mut() {
-            let obstacles = obstacle_query
-                .iter()
-                .map(|pos| pos.pos)
-                .collect::<Vec<Axial>>();
-
-            movement_range.hexes = hex_pos.pos.new_bfs(&obstacles, mp.current);
-        }
+    // a new turn refills MP, a dead unit frees its hex and a new unit blocks one
+    let turn_started = turn_started_event_r.read().count() > 0;
+    let unit_died = unit_died_event_r.read().count() > 0;
+    let unit_moved = unit_moved_event_r.read().count() > 0;
+    let unit_added = !added_unit_query.is_empty();
+
+    if !unit_moved && !turn_started && !unit_died && !unit_added {
+        return;
+    }
+
+    let costs = terrain_costs(&terrain_query);
+
+    for (hex_pos, mp, mut movement_range) in movement_query.iter_mut() {
+        let obstacles = obstacle_query
+            .iter()
+            .map(|pos| pos.pos)
+            .collect::<Vec<Axial>>();
+
+        movement_range.hexes = hex_pos.pos.dijkstra(&obstacles, &costs, mp.current);
     }
 }
 
+/// Redraws the overlay of the selected unit when it moves or another unit gets selected.
 fn respawn_movement_range(
     mp_query: Query<&MovementRange>,
     mut unit_moved_event_r: EventReader<UnitMovedEvent>,
+    selected_unit: Res<CurrentActiveUnit>,
     mut commands: Commands,
-    game_assets: Res<GameAssets>,
+    game_assets: Option<Res<GameAssets>>,
 ) {
-    for unit_moved_e in unit_moved_event_r.read() {
-        let movement_range = mp_query.get(unit_moved_e.entity).unwrap();
-
-        for hex in &movement_range.hexes {
-            commands.spawn((
-                MaterialMesh2dBundle {
-                    mesh: Mesh2dHandle(game_assets.hexagone.clone()),
-                    material: game_assets.movement_hex_color.clone(),
-                    transform: Transform {
-                        translation: hex.hex.to_vec3_pixel().set_z(1.),
-                        ..default()
-                    },
+    let unit_moved = unit_moved_event_r.read().count() > 0;
+
+    if !unit_moved && !selected_unit.is_changed() {
+        return;
+    }
+
+    let Some(movement_range) = selected_unit
+        .entity
+        .and_then(|entity| mp_query.get(entity).ok())
+    else {
+        return;
+    };
+
+    // the overlay is what `movement_hex_clicked` picks from, so it exists even when headless
+    for hex in &movement_range.hexes {
+        let mut entity = commands.spawn((
+            MovementHex {
+                distance: hex.distance,
+            },
+            HexPos { pos: hex.hex },
+            Name::new("MovementHex"),
+        ));
+
+        if let Some(game_assets) = &game_assets {
+            entity.insert(MaterialMesh2dBundle {
+                mesh: Mesh2dHandle(game_assets.hexagone.clone()),
+                material: game_assets.movement_hex_color.clone(),
+                transform: Transform {
+                    translation: hex.hex.to_vec3_pixel().set_z(1.),
                     ..default()
                 },
-                MovementHex {
-                    distance: hex.distance,
-                },
-                HexPos { pos: hex.hex },
-                Name::new("MovementHex"),
-            ));
+                ..default()
+            });
         }
     }
 }
 
 fn despawn_update_movement_range(
     mut unit_moved_event_r: EventReader<UnitMovedEvent>,
+    selected_unit: Res<CurrentActiveUnit>,
     mut commands: Commands,
     movement_hex_query: Query<Entity, With<MovementHex>>,
 ) {
-    for _ in unit_moved_event_r.read() {
-        for entity in movement_hex_query.iter() {
-            commands.entity(entity).despawn();
-        }
+    let unit_moved = unit_moved_event_r.read().count() > 0;
+
+    if !unit_moved && !selected_unit.is_changed() {
+        return;
+    }
+
+    for entity in movement_hex_query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        combat::Targeting,
+        game_log::{GameLog, GameLogEntry},
+        test_harness::TestApp,
+        unit::CurrentActiveUnit,
+        Axial,
+    };
+
+    #[test]
+    fn clicking_a_unit_selects_it() {
+        let mut game = TestApp::new();
+        let unit = game.unit_at(Axial::new(0, 0)).unwrap();
+
+        game.click(Axial::new(0, 0));
+
+        assert_eq!(
+            game.app.world.resource::<CurrentActiveUnit>().entity,
+            Some(unit)
+        );
+    }
+
+    #[test]
+    fn select_then_move() {
+        let mut game = TestApp::new();
+        let unit = game.unit_at(Axial::new(0, 0)).unwrap();
+
+        game.click(Axial::new(0, 0));
+        game.click(Axial::new(0, -2));
+        game.finish_movement();
+
+        assert_eq!(game.hex_pos(unit), Axial::new(0, -2));
+        assert_eq!(game.unit_at(Axial::new(0, 0)), None);
+    }
+
+    #[test]
+    fn moving_deducts_the_path_cost() {
+        let mut game = TestApp::new();
+        let unit = game.unit_at(Axial::new(0, 0)).unwrap();
+
+        // two plains hexes
+        game.click(Axial::new(0, 0));
+        game.click(Axial::new(0, -2));
+        game.finish_movement();
+        assert_eq!(game.mp(unit).current, 2);
+
+        // onto the road
+        game.click(Axial::new(-1, -2));
+        game.finish_movement();
+        assert_eq!(game.mp(unit).current, 1);
+        assert_eq!(game.mp(unit).base, 4);
+    }
+
+    #[test]
+    fn movement_range_follows_the_unit() {
+        let mut game = TestApp::new();
+        let unit = game.unit_at(Axial::new(0, 0)).unwrap();
+        let before = game.movement_range(unit).clone();
+
+        game.click(Axial::new(0, 0));
+        game.click(Axial::new(0, -2));
+        game.finish_movement();
+
+        let range = game.movement_range(unit);
+        assert_ne!(*range, before);
+        assert!(range.iter().all(|hex| hex.distance <= 2));
+        assert!(range.iter().any(|hex| hex.hex == Axial::new(0, 0)));
+        assert!(!range.iter().any(|hex| hex.hex == Axial::new(0, -2)));
+    }
+
+    #[test]
+    fn moving_is_logged() {
+        let mut game = TestApp::new();
+
+        game.click(Axial::new(0, 0));
+        game.click(Axial::new(0, -2));
+        game.finish_movement();
+
+        let entries = &game.app.world.resource::<GameLog>().entries;
+        assert!(entries.contains(&GameLogEntry::Moved {
+            unit: "Unit 1 (team 0)".to_string(),
+            to: Axial::new(0, -2),
+            mp_left: 2,
+        }));
+    }
+
+    #[test]
+    fn clicking_out_of_range_does_not_move() {
+        let mut game = TestApp::new();
+        let unit = game.unit_at(Axial::new(0, 0)).unwrap();
+
+        game.click(Axial::new(0, 0));
+        game.click(Axial::new(5, -6));
+        game.finish_movement();
+
+        assert_eq!(game.hex_pos(unit), Axial::new(0, 0));
+        assert_eq!(game.mp(unit).current, 4);
+    }
+
+    #[test]
+    fn cancelling_deselects_and_clears_the_overlay() {
+        let mut game = TestApp::new();
+
+        game.click(Axial::new(0, 0));
+        assert!(game.movement_hexes() > 0);
+
+        game.cancel();
+
+        assert_eq!(game.app.world.resource::<CurrentActiveUnit>().entity, None);
+        assert_eq!(game.movement_hexes(), 0);
+    }
+
+    #[test]
+    fn clicking_an_empty_hex_out_of_range_deselects() {
+        let mut game = TestApp::new();
+
+        game.click(Axial::new(0, 0));
+        game.click(Axial::new(5, -6));
+
+        assert_eq!(game.app.world.resource::<CurrentActiveUnit>().entity, None);
+        assert_eq!(game.movement_hexes(), 0);
+    }
+
+    #[test]
+    fn missing_an_attack_leaves_targeting() {
+        let mut game = TestApp::new();
+        let unit = game.unit_at(Axial::new(0, 0)).unwrap();
+
+        game.click(Axial::new(0, 0));
+        *game.app.world.resource_mut::<Targeting>() = Targeting::Attack;
+        game.click(Axial::new(0, -2));
+        assert_eq!(*game.app.world.resource::<Targeting>(), Targeting::None);
+        assert_eq!(game.hex_pos(unit), Axial::new(0, 0));
+
+        game.click(Axial::new(0, 0));
+        game.click(Axial::new(0, -2));
+        game.finish_movement();
+        assert_eq!(game.hex_pos(unit), Axial::new(0, -2));
+    }
+
+    #[test]
+    fn clicking_without_a_selection_does_not_move() {
+        let mut game = TestApp::new();
+        let unit = game.unit_at(Axial::new(0, 0)).unwrap();
+
+        game.click(Axial::new(0, -1));
+        game.finish_movement();
+
+        assert_eq!(game.hex_pos(unit), Axial::new(0, 0));
+        assert_eq!(game.mp(unit).current, 4);
     }
 }
@@ -3,7 +3,13 @@ use bevy::{
     sprite::{MaterialMesh2dBundle, Mesh2dHandle},
 };
 
-use crate::{asset_loader::GameAssets, hex_pos::HexPos, schedule::InGameSet, Axial, Vec3Extra};
+use crate::{
+    asset_loader::GameAssets,
+    hex_pos::HexPos,
+    map::{CurrentMap, MapData},
+    schedule::{GameState, InGameSet},
+    Axial, Vec3Extra,
+};
 
 pub struct ObstaclePlugin;
 
@@ -12,35 +18,47 @@ pub struct Obstacle;
 
 impl Plugin for ObstaclePlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(PostStartup, spawn_obstacles.in_set(InGameSet::InitEntities));
+        app.add_systems(
+            OnEnter(GameState::InGame),
+            spawn_obstacles.in_set(InGameSet::InitEntities),
+        );
+    }
+}
+
+fn spawn_obstacles(
+    mut commands: Commands,
+    game_assets: Option<Res<GameAssets>>,
+    current_map: Res<CurrentMap>,
+    maps: Res<Assets<MapData>>,
+) {
+    let Some(map_data) = maps.get(&current_map.handle) else {
+        return;
+    };
+
+    for &pos in &map_data.obstacles {
+        spawn_obstacle(&mut commands, game_assets.as_deref(), pos);
     }
 }
 
-fn spawn_obstacles(mut commands: Commands, game_assets: Res<GameAssets>) {
-    let obstacles = vec![
-        Axial::new(0, 1),
-        Axial::new(1, 1),
-        Axial::new(2, 1),
-        Axial::new(3, 1),
-        Axial::new(-3, 1),
-        Axial::new(-3, 0),
-        Axial::new(-3, -1),
-    ];
-
-    for pos in obstacles {
-        commands.spawn((
-            MaterialMesh2dBundle {
-                mesh: Mesh2dHandle(game_assets.hexagone.clone()),
-                material: game_assets.gray.clone(),
-                transform: Transform {
-                    translation: pos.to_vec3_pixel().set_z(1.),
-                    ..default()
-                },
-                ..default()
-            },
-            Obstacle,
-            HexPos { pos },
-            Name::new("Obstacle"),
-        ));
+pub fn spawn_obstacle(
+    commands: &mut Commands,
+    game_assets: Option<&GameAssets>,
+    pos: Axial,
+) -> Entity {
+    let transform = Transform {
+        translation: pos.to_vec3_pixel().set_z(1.),
+        ..default()
+    };
+    let mut entity = commands.spawn((Obstacle, HexPos { pos }, Name::new("Obstacle")));
+
+    match game_assets {
+        Some(game_assets) => entity.insert(MaterialMesh2dBundle {
+            mesh: Mesh2dHandle(game_assets.hexagone.clone()),
+            material: game_assets.gray.clone(),
+            transform,
+            ..default()
+        }),
+        None => entity.insert(TransformBundle::from_transform(transform)),
     }
+    .id()
 }
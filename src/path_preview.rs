@@ -0,0 +1,134 @@
+use bevy::{
+    prelude::*,
+    sprite::{MaterialMesh2dBundle, Mesh2dHandle},
+};
+
+use crate::{
+    asset_loader::GameAssets,
+    cursor::{CursorGridMoveEvent, CursorGridPos},
+    hex_pos::HexPos,
+    map::{terrain_costs, Hex, Terrain},
+    movement::{MovementAnimation, MovementRange, Mp, UnitMovedEvent},
+    obstacle::Obstacle,
+    schedule::UpdateSet,
+    unit::{CurrentActiveUnit, Unit},
+    Axial, Vec3Extra,
+};
+
+/// Marks the dots and cost label showing the route to the hovered hex.
+#[derive(Component)]
+pub struct PathPreview;
+
+pub struct PathPreviewPlugin;
+
+impl Plugin for PathPreviewPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            update_path_preview
+                .run_if(resource_exists::<GameAssets>)
+                .in_set(UpdateSet::RespawnObjects),
+        );
+    }
+}
+
+fn update_path_preview(
+    mut commands: Commands,
+    mut cursor_grid_move_event_r: EventReader<CursorGridMoveEvent>,
+    mut unit_moved_event_r: EventReader<UnitMovedEvent>,
+    game_assets: Res<GameAssets>,
+    cursor_grid_pos: Res<CursorGridPos>,
+    selected_unit: Res<CurrentActiveUnit>,
+    preview_query: Query<Entity, With<PathPreview>>,
+    started_animation_query: Query<(), Added<MovementAnimation>>,
+    animation_query: Query<(), With<MovementAnimation>>,
+    obstacle_query: Query<&HexPos, With<Obstacle>>,
+    terrain_query: Query<(&HexPos, &Terrain), With<Hex>>,
+    unit_query: Query<(&HexPos, &Mp, &MovementRange), With<Unit>>,
+) {
+    let cursor_moved = cursor_grid_move_event_r.read().count() > 0;
+    let unit_moved = unit_moved_event_r.read().count() > 0;
+
+    // leaving the map sends no event but still clears the preview
+    if !cursor_moved
+        && !cursor_grid_pos.is_changed()
+        && !unit_moved
+        && !selected_unit.is_changed()
+        && started_animation_query.is_empty()
+    {
+        return;
+    }
+
+    for entity in preview_query.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    if !animation_query.is_empty() {
+        return;
+    }
+
+    let Some((unit_pos, mp, movement_range)) = selected_unit
+        .entity
+        .and_then(|entity| unit_query.get(entity).ok())
+    else {
+        return;
+    };
+
+    let Some(goal) = cursor_grid_pos.pos else {
+        return;
+    };
+    if !movement_range.hexes.iter().any(|hex| hex.hex == goal) {
+        return;
+    }
+
+    let obstacles = obstacle_query
+        .iter()
+        .map(|pos| pos.pos)
+        .collect::<Vec<Axial>>();
+    let costs = terrain_costs(&terrain_query);
+
+    let Some(path) = unit_pos
+        .pos
+        .find_path(&goal, &obstacles, &costs, mp.current)
+    else {
+        return;
+    };
+    let cost: usize = path.iter().skip(1).map(|hex| costs[hex]).sum();
+
+    for hex in path.iter().skip(1) {
+        commands.spawn((
+            MaterialMesh2dBundle {
+                mesh: Mesh2dHandle(game_assets.path_dot.clone()),
+                material: game_assets.path_preview_color.clone(),
+                transform: Transform {
+                    translation: hex.to_vec3_pixel().set_z(3.),
+                    ..default()
+                },
+                ..default()
+            },
+            PathPreview,
+            Name::new("PathPreview"),
+        ));
+    }
+
+    commands.spawn((
+        Text2dBundle {
+            text: Text::from_section(
+                format!("{} MP", cost),
+                TextStyle {
+                    font: game_assets.font.clone(),
+                    font_size: 16.0,
+                    color: Color::BLACK,
+                },
+            )
+            .with_justify(JustifyText::Center),
+            transform: Transform {
+                translation: goal.to_vec3_pixel().set_z(11.) + Vec3::new(0., 12., 0.),
+                ..default()
+            },
+            ..default()
+        },
+        PathPreview,
+        Name::new("PathPreviewCost"),
+    ));
+}
@@ -0,0 +1,458 @@
+use std::{fs, path::Path};
+
+use bevy::prelude::*;
+use bevy_inspector_egui::{
+    bevy_egui::{EguiContexts, EguiSettings},
+    egui,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    combat::AttackEvent,
+    hex_pos::HexPos,
+    history::{RedoEvent, UndoEvent},
+    input::{Action, ActionState},
+    map::{CurrentMap, MapShape},
+    movement::{movement_idle, MoveUnitEvent, MovementAnimationSettings},
+    rng::GameRng,
+    save::{LoadGameEvent, SaveGame},
+    schedule::{GameState, UpdateSet},
+    turn::{EndTurnEvent, TurnMode},
+    unit::{CurrentActiveUnit, Unit},
+    Axial,
+};
+
+/// Bumped whenever `Replay` changes shape, older files are refused.
+pub const REPLAY_VERSION: u32 = 2;
+
+const REPLAY_PATH: &str = "replays/last.replay.ron";
+
+/// Seconds between two commands during playback.
+const PLAYBACK_DELAY: f32 = 0.5;
+const FAST_FORWARD: f32 = 4.;
+
+/// Units are referred to by where they stand, entities differ from one run to the next.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ReplayAction {
+    Move {
+        from: Axial,
+        to: Axial,
+    },
+    Attack {
+        attacker: Axial,
+        target: Axial,
+    },
+    EndTurn,
+    Undo,
+    Redo,
+    /// The whole save is kept, the quicksave file may be gone or overwritten by playback time.
+    Load {
+        save: SaveGame,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayCommand {
+    /// Number of turns ended before this command was given.
+    pub turn: usize,
+    pub action: ReplayAction,
+}
+
+/// Everything needed to rebuild a match from scratch, as long as the map file is unchanged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub seed: u64,
+    pub map: String,
+    pub shape: Option<MapShape>,
+    pub mode: TurnMode,
+    pub commands: Vec<ReplayCommand>,
+}
+
+impl Replay {
+    pub fn read(path: &str) -> Result<Replay, String> {
+        let content = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        let replay = ron::de::from_str::<Replay>(&content).map_err(|error| error.to_string())?;
+
+        if replay.version != REPLAY_VERSION {
+            return Err(format!(
+                "replay version {} is not supported, expected {}",
+                replay.version, REPLAY_VERSION
+            ));
+        }
+
+        Ok(replay)
+    }
+
+    fn write(&self, path: &str) -> Result<(), String> {
+        let content = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|error| error.to_string())?;
+
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent).map_err(|error| error.to_string())?;
+        }
+        fs::write(path, content).map_err(|error| error.to_string())
+    }
+}
+
+/// Sent by the systems carrying out gameplay commands as they do so.
+/// Writers of a single event type never run in parallel, so reading them back keeps the order
+/// commands took effect in, whichever system ran first.
+#[derive(Event, Debug, Clone)]
+pub struct CommandAppliedEvent {
+    pub action: ReplayAction,
+}
+
+#[derive(Resource, Debug)]
+pub struct ReplayRecorder {
+    pub replay: Replay,
+    pub turn: usize,
+}
+
+#[derive(Resource, Debug)]
+pub struct ReplayPlayback {
+    pub commands: Vec<ReplayCommand>,
+    /// Index of the next command to play.
+    pub next: usize,
+    pub paused: bool,
+    /// Plays a single command while paused.
+    pub step: bool,
+    pub fast_forward: bool,
+    pub timer: Timer,
+}
+
+impl ReplayPlayback {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            commands: replay.commands,
+            next: 0,
+            paused: false,
+            step: false,
+            fast_forward: false,
+            timer: Timer::from_seconds(PLAYBACK_DELAY, TimerMode::Once),
+        }
+    }
+}
+
+/// Run condition holding back player input while a replay is playing.
+pub fn not_replaying(playback: Option<Res<ReplayPlayback>>) -> bool {
+    playback.is_none()
+}
+
+/// Where the recording is kept, rewritten as commands come in so a crash does not lose it.
+#[derive(Resource, Debug)]
+struct ReplayFile {
+    path: String,
+}
+
+/// Records the match to `path`, or plays `playback` back instead.
+pub struct ReplayPlugin {
+    pub playback: Option<Replay>,
+    /// The match is still recorded in `ReplayRecorder` without one.
+    pub path: Option<String>,
+}
+
+impl Default for ReplayPlugin {
+    fn default() -> Self {
+        Self {
+            playback: None,
+            path: Some(REPLAY_PATH.to_string()),
+        }
+    }
+}
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<CommandAppliedEvent>();
+
+        match &self.playback {
+            Some(replay) => {
+                app.insert_resource(ReplayPlayback::new(replay.clone()))
+                    .add_systems(
+                        Update,
+                        (
+                            playback_controls.run_if(resource_exists::<EguiSettings>),
+                            playback_keys,
+                            play_replay.run_if(movement_idle),
+                        )
+                            .chain()
+                            .in_set(UpdateSet::UserInput),
+                    );
+            }
+            None => {
+                app.add_systems(OnEnter(GameState::InGame), start_recording)
+                    .add_systems(
+                        Update,
+                        record_commands
+                            .run_if(resource_exists::<ReplayRecorder>)
+                            .after(UpdateSet::Combat),
+                    );
+
+                if let Some(path) = &self.path {
+                    app.insert_resource(ReplayFile { path: path.clone() });
+                }
+            }
+        }
+    }
+}
+
+fn start_recording(
+    mut commands: Commands,
+    game_rng: Res<GameRng>,
+    current_map: Res<CurrentMap>,
+    turn_mode: Res<TurnMode>,
+) {
+    commands.insert_resource(ReplayRecorder {
+        replay: Replay {
+            version: REPLAY_VERSION,
+            seed: game_rng.seed(),
+            map: current_map.path.clone(),
+            shape: current_map.shape.clone(),
+            mode: *turn_mode,
+            commands: Vec::new(),
+        },
+        turn: 0,
+    });
+}
+
+fn record_commands(
+    mut recorder: ResMut<ReplayRecorder>,
+    mut command_applied_event_r: EventReader<CommandAppliedEvent>,
+    replay_file: Option<Res<ReplayFile>>,
+) {
+    if command_applied_event_r.is_empty() {
+        return;
+    }
+
+    for event in command_applied_event_r.read() {
+        let turn = recorder.turn;
+        recorder.replay.commands.push(ReplayCommand {
+            turn,
+            action: event.action.clone(),
+        });
+
+        if let ReplayAction::EndTurn = event.action {
+            recorder.turn += 1;
+        }
+    }
+
+    if let Some(replay_file) = replay_file {
+        if let Err(error) = recorder.replay.write(&replay_file.path) {
+            error!("could not save replay to {}: {}", replay_file.path, error);
+        }
+    }
+}
+
+fn set_fast_forward(
+    playback: &mut ReplayPlayback,
+    settings: &mut MovementAnimationSettings,
+    fast_forward: bool,
+) {
+    if playback.fast_forward == fast_forward {
+        return;
+    }
+
+    playback.fast_forward = fast_forward;
+    if fast_forward {
+        settings.speed *= FAST_FORWARD;
+    } else {
+        settings.speed /= FAST_FORWARD;
+    }
+}
+
+fn playback_controls(
+    mut contexts: EguiContexts,
+    mut playback: ResMut<ReplayPlayback>,
+    mut settings: ResMut<MovementAnimationSettings>,
+) {
+    egui::Window::new("Replay")
+        .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-10., 10.))
+        .resizable(false)
+        .collapsible(false)
+        .show(contexts.ctx_mut(), |ui| {
+            match playback.commands.get(playback.next) {
+                Some(command) => ui.label(format!(
+                    "Command {}/{}, turn {}",
+                    playback.next + 1,
+                    playback.commands.len(),
+                    command.turn + 1
+                )),
+                None => ui.label("Replay finished"),
+            };
+
+            ui.horizontal(|ui| {
+                let label = if playback.paused { "Play" } else { "Pause" };
+                if ui.button(label).clicked() {
+                    playback.paused = !playback.paused;
+                }
+
+                if ui
+                    .add_enabled(playback.paused, egui::Button::new("Step"))
+                    .clicked()
+                {
+                    playback.step = true;
+                }
+
+                let mut fast_forward = playback.fast_forward;
+                if ui.toggle_value(&mut fast_forward, "Fast forward").changed() {
+                    set_fast_forward(&mut playback, &mut settings, fast_forward);
+                }
+            });
+        });
+}
+
+fn playback_keys(
+    actions: Res<ActionState>,
+    mut playback: ResMut<ReplayPlayback>,
+    mut settings: ResMut<MovementAnimationSettings>,
+) {
+    if actions.just_pressed(Action::ReplayPause) {
+        playback.paused = !playback.paused;
+    }
+    if actions.just_pressed(Action::ReplayStep) && playback.paused {
+        playback.step = true;
+    }
+    if actions.just_pressed(Action::ReplayFastForward) {
+        let fast_forward = !playback.fast_forward;
+        set_fast_forward(&mut playback, &mut settings, fast_forward);
+    }
+}
+
+fn play_replay(
+    time: Res<Time>,
+    mut playback: ResMut<ReplayPlayback>,
+    mut selected_unit: ResMut<CurrentActiveUnit>,
+    mut move_unit_event_w: EventWriter<MoveUnitEvent>,
+    mut attack_event_w: EventWriter<AttackEvent>,
+    mut end_turn_event_w: EventWriter<EndTurnEvent>,
+    mut undo_event_w: EventWriter<UndoEvent>,
+    mut redo_event_w: EventWriter<RedoEvent>,
+    mut load_game_event_w: EventWriter<LoadGameEvent>,
+    unit_query: Query<(Entity, &HexPos), With<Unit>>,
+) {
+    let speed = if playback.fast_forward {
+        FAST_FORWARD
+    } else {
+        1.
+    };
+    playback.timer.tick(time.delta().mul_f32(speed));
+
+    if playback.paused {
+        if !playback.step {
+            return;
+        }
+        playback.step = false;
+    } else if !playback.timer.finished() {
+        return;
+    }
+
+    let Some(command) = playback.commands.get(playback.next).cloned() else {
+        return;
+    };
+    playback.next += 1;
+    playback.timer.reset();
+
+    let unit_at = |pos: Axial| {
+        let unit = unit_query
+            .iter()
+            .find(|(_, hex_pos)| hex_pos.pos == pos)
+            .map(|(entity, _)| entity);
+        if unit.is_none() {
+            warn!(
+                "replay out of sync on turn {}: no unit at {:?}",
+                command.turn + 1,
+                pos
+            );
+        }
+        unit
+    };
+
+    match command.action {
+        ReplayAction::Move { from, to } => {
+            if let Some(entity) = unit_at(from) {
+                selected_unit.entity = Some(entity);
+                move_unit_event_w.send(MoveUnitEvent { entity, to });
+            }
+        }
+        ReplayAction::Attack { attacker, target } => {
+            if let (Some(attacker), Some(target)) = (unit_at(attacker), unit_at(target)) {
+                selected_unit.entity = Some(attacker);
+                attack_event_w.send(AttackEvent { attacker, target });
+            }
+        }
+        ReplayAction::EndTurn => {
+            end_turn_event_w.send(EndTurnEvent);
+        }
+        ReplayAction::Undo => {
+            undo_event_w.send(UndoEvent);
+        }
+        ReplayAction::Redo => {
+            redo_event_w.send(RedoEvent);
+        }
+        ReplayAction::Load { save } => {
+            load_game_event_w.send(LoadGameEvent { save });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        history::{CommandHistory, GameCommand},
+        test_harness::TestApp,
+    };
+
+    #[test]
+    fn commands_are_recorded_in_the_order_they_apply() {
+        let mut game = TestApp::new();
+        let unit = game.unit_at(Axial::new(0, 0)).unwrap();
+
+        game.app.world.send_event(EndTurnEvent);
+        game.app.world.send_event(MoveUnitEvent {
+            entity: unit,
+            to: Axial::new(0, -2),
+        });
+        game.finish_movement();
+
+        let applied = game
+            .app
+            .world
+            .resource::<CommandHistory>()
+            .done
+            .iter()
+            .map(|command| matches!(command, GameCommand::EndTurn))
+            .collect::<Vec<bool>>();
+        let recorded = game
+            .app
+            .world
+            .resource::<ReplayRecorder>()
+            .replay
+            .commands
+            .iter()
+            .map(|command| matches!(command.action, ReplayAction::EndTurn))
+            .collect::<Vec<bool>>();
+
+        assert_eq!(applied.len(), 2);
+        assert_eq!(recorded, applied);
+    }
+
+    #[test]
+    fn recording_is_written_as_commands_apply() {
+        let mut game = TestApp::new();
+        let path = std::env::temp_dir().join(format!("tactics-{}.replay.ron", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        game.app
+            .world
+            .insert_resource(ReplayFile { path: path.clone() });
+
+        game.app.world.send_event(EndTurnEvent);
+        game.update();
+
+        let replay = Replay::read(&path);
+        let _ = fs::remove_file(&path);
+        let commands = replay.unwrap().commands;
+        assert_eq!(commands.len(), 1);
+        assert!(matches!(commands[0].action, ReplayAction::EndTurn));
+    }
+}
@@ -0,0 +1,67 @@
+use bevy::prelude::*;
+use rand::{Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// Single source of gameplay randomness, so a seed replays a whole match.
+#[derive(Resource, Debug, Clone)]
+pub struct GameRng {
+    seed: u64,
+    rng: ChaCha8Rng,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// How far along the stream the generator is, restoring it resumes the exact same rolls.
+    pub fn word_pos(&self) -> u128 {
+        self.rng.get_word_pos()
+    }
+
+    pub fn restore(seed: u64, word_pos: u128) -> Self {
+        let mut game_rng = Self::new(seed);
+        game_rng.rng.set_word_pos(word_pos);
+        game_rng
+    }
+}
+
+impl RngCore for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
+
+#[derive(Default)]
+pub struct RngPlugin {
+    /// Fixed seed for reproducible matches, a random one is picked and logged otherwise.
+    pub seed: Option<u64>,
+}
+
+impl Plugin for RngPlugin {
+    fn build(&self, app: &mut App) {
+        let seed = self.seed.unwrap_or_else(|| rand::thread_rng().gen());
+        info!("game seed: {}", seed);
+
+        app.insert_resource(GameRng::new(seed));
+    }
+}
@@ -0,0 +1,383 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    asset_loader::GameAssets,
+    combat::{Attack, Defense, Health, Targeting},
+    hex_pos::HexPos,
+    history::CommandHistory,
+    input::{Action, ActionState},
+    map::{spawn_hex, Hex, Terrain, UnitSpawn},
+    movement::{movement_idle, MovementHex, Mp},
+    obstacle::{spawn_obstacle, Obstacle},
+    path_preview::PathPreview,
+    replay::{not_replaying, CommandAppliedEvent, ReplayAction},
+    rng::GameRng,
+    schedule::UpdateSet,
+    turn::{Initiative, Team, TurnManager, TurnMode, TurnQueue},
+    unit::{spawn_unit, CurrentActiveUnit, Unit},
+    Axial,
+};
+
+/// Bumped whenever `SaveGame` changes shape, older files are refused.
+pub const SAVE_VERSION: u32 = 2;
+
+const QUICKSAVE_PATH: &str = "saves/quicksave.ron";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedUnit {
+    pub spawn: UnitSpawn,
+    pub mp: usize,
+    pub hp: usize,
+    pub attack_spent: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedTurns {
+    pub mode: TurnMode,
+    pub teams: Vec<usize>,
+    pub active: usize,
+    pub round: usize,
+    /// Indices into `SaveGame::units`, in `TurnMode::Initiative` play order.
+    pub queue: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedRng {
+    pub seed: u64,
+    pub word_pos: u128,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveGame {
+    pub version: u32,
+    pub rng: SavedRng,
+    pub hexes: Vec<(Axial, Terrain)>,
+    pub obstacles: Vec<Axial>,
+    pub units: Vec<SavedUnit>,
+    pub turns: SavedTurns,
+}
+
+/// Units spawned by `load_game`, their turn order comes from the save rather than their initiative.
+#[derive(Component, Debug)]
+pub struct Restored;
+
+/// Asks for `save` to replace the current game, sent by quickloads and replays alike.
+#[derive(Event, Debug, Clone)]
+pub struct LoadGameEvent {
+    pub save: SaveGame,
+}
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<LoadGameEvent>().add_systems(
+            Update,
+            (
+                (
+                    snapshot_game.pipe(save_game).run_if(quick_save_pressed),
+                    quick_load,
+                )
+                    .run_if(not_replaying),
+                load_game,
+            )
+                .chain()
+                .run_if(movement_idle)
+                .in_set(UpdateSet::UserInput),
+        );
+    }
+}
+
+fn quick_save_pressed(actions: Res<ActionState>) -> bool {
+    actions.just_pressed(Action::QuickSave)
+}
+
+/// The current game as it would be saved.
+pub fn snapshot_game(
+    game_rng: Res<GameRng>,
+    turn_mode: Res<TurnMode>,
+    turn_manager: Res<TurnManager>,
+    turn_queue: Res<TurnQueue>,
+    hex_query: Query<(&HexPos, &Terrain), With<Hex>>,
+    obstacle_query: Query<&HexPos, (With<Obstacle>, Without<Unit>)>,
+    unit_query: Query<
+        (
+            Entity,
+            &Name,
+            &HexPos,
+            &Mp,
+            &Team,
+            &Initiative,
+            &Health,
+            &Attack,
+            &Defense,
+        ),
+        With<Unit>,
+    >,
+) -> SaveGame {
+    let mut indices: HashMap<Entity, usize> = HashMap::new();
+    let units = unit_query
+        .iter()
+        .enumerate()
+        .map(
+            |(index, (entity, name, pos, mp, team, initiative, health, attack, defense))| {
+                indices.insert(entity, index);
+                SavedUnit {
+                    spawn: UnitSpawn {
+                        name: Some(name.to_string()),
+                        pos: pos.pos,
+                        mp: mp.base,
+                        team: team.id,
+                        initiative: initiative.value,
+                        hp: health.max,
+                        damage: attack.damage,
+                        range: attack.range,
+                        defense: defense.value,
+                    },
+                    mp: mp.current,
+                    hp: health.current,
+                    attack_spent: attack.spent,
+                }
+            },
+        )
+        .collect::<Vec<SavedUnit>>();
+
+    SaveGame {
+        version: SAVE_VERSION,
+        rng: SavedRng {
+            seed: game_rng.seed(),
+            word_pos: game_rng.word_pos(),
+        },
+        hexes: hex_query
+            .iter()
+            .map(|(pos, terrain)| (pos.pos, *terrain))
+            .collect(),
+        obstacles: obstacle_query.iter().map(|pos| pos.pos).collect(),
+        units,
+        turns: SavedTurns {
+            mode: *turn_mode,
+            teams: turn_manager.teams.clone(),
+            active: turn_manager.active,
+            round: match *turn_mode {
+                TurnMode::Teams => turn_manager.round,
+                TurnMode::Initiative => turn_queue.round,
+            },
+            queue: turn_queue
+                .queue
+                .iter()
+                .filter_map(|entity| indices.get(entity).copied())
+                .collect(),
+        },
+    }
+}
+
+fn save_game(In(save_game): In<SaveGame>) {
+    let result = ron::ser::to_string_pretty(&save_game, ron::ser::PrettyConfig::default())
+        .map_err(|error| error.to_string())
+        .and_then(|content| {
+            if let Some(parent) = Path::new(QUICKSAVE_PATH).parent() {
+                fs::create_dir_all(parent).map_err(|error| error.to_string())?;
+            }
+            fs::write(QUICKSAVE_PATH, content).map_err(|error| error.to_string())
+        });
+
+    match result {
+        Ok(()) => info!("game saved to {}", QUICKSAVE_PATH),
+        Err(error) => error!("could not save game to {}: {}", QUICKSAVE_PATH, error),
+    }
+}
+
+fn read_save(path: &str) -> Result<SaveGame, String> {
+    let content = fs::read_to_string(path).map_err(|error| error.to_string())?;
+    let save_game = ron::de::from_str::<SaveGame>(&content).map_err(|error| error.to_string())?;
+
+    if save_game.version != SAVE_VERSION {
+        return Err(format!(
+            "save version {} is not supported, expected {}",
+            save_game.version, SAVE_VERSION
+        ));
+    }
+
+    Ok(save_game)
+}
+
+fn quick_load(actions: Res<ActionState>, mut load_game_event_w: EventWriter<LoadGameEvent>) {
+    if !actions.just_pressed(Action::QuickLoad) {
+        return;
+    }
+
+    match read_save(QUICKSAVE_PATH) {
+        Ok(save) => {
+            load_game_event_w.send(LoadGameEvent { save });
+        }
+        Err(error) => error!("could not load game from {}: {}", QUICKSAVE_PATH, error),
+    }
+}
+
+fn load_game(
+    mut commands: Commands,
+    mut load_game_event_r: EventReader<LoadGameEvent>,
+    mut command_applied_event_w: EventWriter<CommandAppliedEvent>,
+    game_assets: Option<Res<GameAssets>>,
+    mut game_rng: ResMut<GameRng>,
+    mut turn_mode: ResMut<TurnMode>,
+    mut turn_manager: ResMut<TurnManager>,
+    mut turn_queue: ResMut<TurnQueue>,
+    mut selected_unit: ResMut<CurrentActiveUnit>,
+    mut history: ResMut<CommandHistory>,
+    mut targeting: ResMut<Targeting>,
+    despawn_query: Query<
+        Entity,
+        Or<(
+            With<Hex>,
+            With<Obstacle>,
+            With<MovementHex>,
+            With<PathPreview>,
+        )>,
+    >,
+) {
+    // only the last load matters, the earlier ones would be wiped right away
+    let Some(save_game) = load_game_event_r
+        .read()
+        .last()
+        .map(|event| event.save.clone())
+    else {
+        return;
+    };
+
+    command_applied_event_w.send(CommandAppliedEvent {
+        action: ReplayAction::Load {
+            save: save_game.clone(),
+        },
+    });
+
+    for entity in despawn_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    for &(hex, terrain) in &save_game.hexes {
+        spawn_hex(&mut commands, game_assets.as_deref(), hex, terrain);
+    }
+
+    for &pos in &save_game.obstacles {
+        spawn_obstacle(&mut commands, game_assets.as_deref(), pos);
+    }
+
+    // movement ranges are rebuilt by `recalc_movement_range` once the units are added
+    let units = save_game
+        .units
+        .iter()
+        .map(|unit| {
+            let entity = spawn_unit(&mut commands, game_assets.as_deref(), &unit.spawn);
+            commands.entity(entity).insert((
+                Restored,
+                Mp {
+                    base: unit.spawn.mp,
+                    current: unit.mp,
+                },
+                Health {
+                    max: unit.spawn.hp,
+                    current: unit.hp,
+                },
+                Attack {
+                    damage: unit.spawn.damage,
+                    range: unit.spawn.range,
+                    spent: unit.attack_spent,
+                },
+            ));
+            entity
+        })
+        .collect::<Vec<Entity>>();
+
+    *game_rng = GameRng::restore(save_game.rng.seed, save_game.rng.word_pos);
+
+    let turns = save_game.turns;
+    *turn_mode = turns.mode;
+    *turn_manager = TurnManager {
+        teams: turns.teams,
+        active: turns.active,
+        round: turns.round,
+    };
+    *turn_queue = TurnQueue {
+        queue: turns
+            .queue
+            .iter()
+            .filter_map(|&index| units.get(index).copied())
+            .collect(),
+        round: turns.round,
+        active_team: turns
+            .queue
+            .first()
+            .and_then(|&index| save_game.units.get(index))
+            .map(|unit| unit.spawn.team),
+    };
+
+    selected_unit.entity = match turns.mode {
+        TurnMode::Teams => None,
+        TurnMode::Initiative => turn_queue.active_unit(),
+    };
+    history.done.clear();
+    history.undone.clear();
+    *targeting = Targeting::None;
+
+    info!("game loaded");
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::{test_harness::TestApp, turn::EndTurnEvent};
+
+    #[test]
+    fn units_that_played_keep_their_turn_spent_after_a_load() {
+        let mut game = TestApp::with_turn_mode(TurnMode::Initiative);
+        let first = game
+            .app
+            .world
+            .resource::<TurnQueue>()
+            .active_unit()
+            .unwrap();
+        let first_name = game.app.world.get::<Name>(first).unwrap().clone();
+
+        game.app.world.send_event(EndTurnEvent);
+        game.update();
+        let save = game.app.world.run_system_once(snapshot_game);
+        let round = save.turns.round;
+        game.app.world.send_event(LoadGameEvent { save });
+        game.advance(2);
+
+        let mut played = Vec::new();
+        while game.app.world.resource::<TurnQueue>().round == round {
+            let unit = game
+                .app
+                .world
+                .resource::<TurnQueue>()
+                .active_unit()
+                .unwrap();
+            played.push(game.app.world.get::<Name>(unit).unwrap().clone());
+            game.app.world.send_event(EndTurnEvent);
+            game.update();
+        }
+
+        assert_eq!(played.len(), 3);
+        assert!(!played.contains(&first_name));
+    }
+
+    #[test]
+    fn rng_position_past_u64_survives_a_save() {
+        let word_pos = u64::MAX as u128 + 16;
+        let saved = SavedRng { seed: 7, word_pos };
+
+        let content = ron::ser::to_string(&saved).unwrap();
+        let loaded = ron::de::from_str::<SavedRng>(&content).unwrap();
+        assert_eq!(loaded.word_pos, word_pos);
+
+        let restored = GameRng::restore(loaded.seed, loaded.word_pos);
+        assert_eq!(restored.word_pos(), word_pos);
+    }
+}
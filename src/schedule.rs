@@ -2,15 +2,26 @@ use bevy::prelude::*;
 
 pub struct SchedulePlugin;
 
+#[derive(States, Debug, Hash, PartialEq, Eq, Clone, Copy, Default)]
+pub enum GameState {
+    /// Waiting for the map file to finish loading.
+    #[default]
+    Loading,
+    InGame,
+}
+
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Hash, PartialEq, Eq, Clone, SystemSet)]
 pub enum InGameSet {
     InitEntities,
     InitMovementRange,
+    InitTurns,
 }
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone, SystemSet)]
 pub enum UpdateSet {
     UserInput,
+    Combat,
     StateCalc,
     DespawnObjects,
     RespawnObjects,
@@ -18,31 +29,39 @@ pub enum UpdateSet {
 
 impl Plugin for SchedulePlugin {
     fn build(&self, app: &mut App) {
-        app.configure_sets(
-            PostStartup,
-            (InGameSet::InitEntities, InGameSet::InitMovementRange).chain(),
-        )
-        .add_systems(
-            PostStartup,
-            apply_deferred
-                .after(InGameSet::InitEntities)
-                .before(InGameSet::InitMovementRange),
-        )
-        .configure_sets(
-            Update,
-            (
-                UpdateSet::UserInput,
-                UpdateSet::StateCalc,
-                UpdateSet::DespawnObjects,
-                UpdateSet::RespawnObjects,
+        app.init_state::<GameState>()
+            .configure_sets(
+                OnEnter(GameState::InGame),
+                (
+                    InGameSet::InitEntities,
+                    InGameSet::InitMovementRange,
+                    InGameSet::InitTurns,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                OnEnter(GameState::InGame),
+                apply_deferred
+                    .after(InGameSet::InitEntities)
+                    .before(InGameSet::InitMovementRange),
+            )
+            .configure_sets(
+                Update,
+                (
+                    UpdateSet::UserInput,
+                    UpdateSet::Combat,
+                    UpdateSet::StateCalc,
+                    UpdateSet::DespawnObjects,
+                    UpdateSet::RespawnObjects,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::InGame)),
             )
-                .chain(),
-        )
-        .add_systems(
-            PostStartup,
-            apply_deferred
-                .after(UpdateSet::DespawnObjects)
-                .before(UpdateSet::RespawnObjects),
-        );
+            .add_systems(
+                PostStartup,
+                apply_deferred
+                    .after(UpdateSet::DespawnObjects)
+                    .before(UpdateSet::RespawnObjects),
+            );
     }
 }
@@ -0,0 +1,136 @@
+use std::time::{Duration, Instant};
+
+use bevy::{prelude::*, time::TimeUpdateStrategy};
+
+use crate::{
+    cursor::{CursorCancelEvent, CursorGridClickEvent},
+    hex_pos::HexPos,
+    map::MapPlugin,
+    movement::{MovementAnimation, MovementHex, MovementRange, Mp},
+    rng::RngPlugin,
+    schedule::GameState,
+    turn::{TurnMode, TurnPlugin},
+    unit::Unit,
+    Axial, HeadlessPlugins, HexDistance,
+};
+
+const FRAME: Duration = Duration::from_nanos(16_666_667);
+const LOAD_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_FRAMES: usize = 1_000;
+
+/// Headless game on a fixed seed, stepped one frame at a time at a steady 60 fps.
+pub struct TestApp {
+    pub app: App,
+}
+
+impl TestApp {
+    pub fn new() -> Self {
+        Self::with_map("maps/default.map.ron")
+    }
+
+    /// Builds the game on `path`, relative to the `assets` folder, and waits until it is in play.
+    pub fn with_map(path: &str) -> Self {
+        Self::build(path, TurnMode::Teams)
+    }
+
+    /// Builds the game on the default map, played in `mode`.
+    pub fn with_turn_mode(mode: TurnMode) -> Self {
+        Self::build("maps/default.map.ron", mode)
+    }
+
+    fn build(path: &str, mode: TurnMode) -> Self {
+        let mut app = App::new();
+        app.add_plugins(
+            HeadlessPlugins
+                .set(RngPlugin { seed: Some(0) })
+                .set(MapPlugin {
+                    path: path.to_string(),
+                    shape: None,
+                })
+                .set(TurnPlugin { mode }),
+        )
+        .insert_resource(TimeUpdateStrategy::ManualDuration(FRAME));
+
+        let started = Instant::now();
+        while *app.world.resource::<State<GameState>>().get() != GameState::InGame {
+            assert!(
+                started.elapsed() < LOAD_TIMEOUT,
+                "map {} did not load in time",
+                path
+            );
+            app.update();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        // let the `OnEnter(GameState::InGame)` commands land
+        app.update();
+
+        Self { app }
+    }
+
+    pub fn update(&mut self) {
+        self.app.update();
+    }
+
+    pub fn advance(&mut self, frames: usize) {
+        for _ in 0..frames {
+            self.app.update();
+        }
+    }
+
+    /// Clicks on `pos` as the cursor would, then runs a frame.
+    pub fn click(&mut self, pos: Axial) {
+        self.app.world.send_event(CursorGridClickEvent { pos });
+        self.app.update();
+    }
+
+    /// Cancels as the cursor would, then runs a frame.
+    pub fn cancel(&mut self) {
+        self.app.world.send_event(CursorCancelEvent);
+        self.app.update();
+    }
+
+    pub fn movement_hexes(&mut self) -> usize {
+        let mut movement_hex_query = self.app.world.query_filtered::<(), With<MovementHex>>();
+        movement_hex_query.iter(&self.app.world).count()
+    }
+
+    /// Runs frames until no unit is walking anymore.
+    pub fn finish_movement(&mut self) {
+        for _ in 0..MAX_FRAMES {
+            let mut animation_query = self
+                .app
+                .world
+                .query_filtered::<(), With<MovementAnimation>>();
+            if animation_query.iter(&self.app.world).next().is_none() {
+                // one more frame for the follow-up systems of `UnitMovedEvent`
+                self.app.update();
+                return;
+            }
+            self.app.update();
+        }
+        panic!("units were still moving after {} frames", MAX_FRAMES);
+    }
+
+    pub fn unit_at(&mut self, pos: Axial) -> Option<Entity> {
+        let mut unit_query = self
+            .app
+            .world
+            .query_filtered::<(Entity, &HexPos), With<Unit>>();
+        unit_query
+            .iter(&self.app.world)
+            .find(|(_, hex_pos)| hex_pos.pos == pos)
+            .map(|(entity, _)| entity)
+    }
+
+    pub fn hex_pos(&self, entity: Entity) -> Axial {
+        self.app.world.get::<HexPos>(entity).unwrap().pos
+    }
+
+    pub fn mp(&self, entity: Entity) -> &Mp {
+        self.app.world.get::<Mp>(entity).unwrap()
+    }
+
+    pub fn movement_range(&self, entity: Entity) -> &Vec<HexDistance> {
+        &self.app.world.get::<MovementRange>(entity).unwrap().hexes
+    }
+}
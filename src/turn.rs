@@ -0,0 +1,403 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy_inspector_egui::{
+    bevy_egui::{EguiContexts, EguiSettings},
+    egui,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    history::{CommandHistory, GameCommand},
+    input::{Action, ActionState},
+    movement::{movement_idle, MovementAnimation, Mp},
+    replay::{not_replaying, CommandAppliedEvent, ReplayAction, ReplayPlayback},
+    save::Restored,
+    schedule::{GameState, InGameSet, UpdateSet},
+    unit::{CurrentActiveUnit, Unit},
+};
+
+#[derive(
+    Component, Reflect, Debug, Hash, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize,
+)]
+#[reflect(Component)]
+pub struct Team {
+    pub id: usize,
+}
+
+/// Per-unit speed stat, higher values act first in `TurnMode::Initiative`.
+#[derive(Component, Reflect, Debug, Clone, Copy, Default)]
+#[reflect(Component)]
+pub struct Initiative {
+    pub value: usize,
+}
+
+#[derive(Resource, Debug, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum TurnMode {
+    /// Teams play one after the other, moving any of their units.
+    #[default]
+    Teams,
+    /// Units play one at a time, ordered by `Initiative`.
+    Initiative,
+}
+
+/// Teams taking part in the match, in play order.
+#[derive(Resource, Default, Debug)]
+pub struct TurnManager {
+    pub teams: Vec<usize>,
+    pub active: usize,
+    pub round: usize,
+}
+
+impl TurnManager {
+    pub fn active_team(&self) -> Option<usize> {
+        self.teams.get(self.active).copied()
+    }
+
+    pub fn is_active(&self, team: &Team) -> bool {
+        self.active_team() == Some(team.id)
+    }
+}
+
+/// Units still to play this round in `TurnMode::Initiative`, the front one is playing.
+#[derive(Resource, Default, Debug)]
+pub struct TurnQueue {
+    pub queue: VecDeque<Entity>,
+    pub round: usize,
+    /// Team of the playing unit, kept around for when that unit dies mid-turn.
+    pub active_team: Option<usize>,
+}
+
+impl TurnQueue {
+    pub fn active_unit(&self) -> Option<Entity> {
+        self.queue.front().copied()
+    }
+}
+
+#[derive(Event, Debug)]
+pub struct EndTurnEvent;
+
+#[derive(Event, Debug)]
+pub struct TurnStartedEvent {
+    pub team: usize,
+    /// Unit whose turn it is, only set in `TurnMode::Initiative`.
+    pub unit: Option<Entity>,
+}
+
+#[derive(Event, Debug)]
+pub struct TurnEndedEvent {
+    pub team: usize,
+}
+
+#[derive(Default)]
+pub struct TurnPlugin {
+    pub mode: TurnMode,
+}
+
+impl Plugin for TurnPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Team>()
+            .register_type::<Initiative>()
+            .insert_resource(self.mode)
+            .init_resource::<TurnManager>()
+            .init_resource::<TurnQueue>()
+            .add_event::<EndTurnEvent>()
+            .add_event::<TurnStartedEvent>()
+            .add_event::<TurnEndedEvent>()
+            .add_systems(
+                OnEnter(GameState::InGame),
+                (
+                    init_turns.run_if(resource_equals(TurnMode::Teams)),
+                    init_turn_queue.run_if(resource_equals(TurnMode::Initiative)),
+                )
+                    .in_set(InGameSet::InitTurns),
+            )
+            .add_systems(
+                Update,
+                (
+                    end_turn_button.run_if(resource_exists::<EguiSettings>),
+                    end_turn_key.run_if(movement_idle).run_if(not_replaying),
+                    end_turn.run_if(resource_equals(TurnMode::Teams)),
+                    (update_turn_queue, end_unit_turn)
+                        .chain()
+                        .run_if(resource_equals(TurnMode::Initiative)),
+                )
+                    .chain()
+                    .in_set(UpdateSet::UserInput),
+            );
+    }
+}
+
+fn init_turns(
+    mut turn_manager: ResMut<TurnManager>,
+    mut turn_started_event_w: EventWriter<TurnStartedEvent>,
+    unit_query: Query<&Team, With<Unit>>,
+) {
+    let mut teams = unit_query
+        .iter()
+        .map(|team| team.id)
+        .collect::<Vec<usize>>();
+    teams.sort();
+    teams.dedup();
+
+    *turn_manager = TurnManager {
+        teams,
+        active: 0,
+        round: 1,
+    };
+
+    if let Some(team) = turn_manager.active_team() {
+        turn_started_event_w.send(TurnStartedEvent { team, unit: None });
+    }
+}
+
+fn end_turn_key(actions: Res<ActionState>, mut end_turn_event_w: EventWriter<EndTurnEvent>) {
+    if actions.just_pressed(Action::EndTurn) {
+        end_turn_event_w.send(EndTurnEvent);
+    }
+}
+
+fn end_turn_button(
+    mut contexts: EguiContexts,
+    turn_mode: Res<TurnMode>,
+    turn_manager: Res<TurnManager>,
+    turn_queue: Res<TurnQueue>,
+    unit_query: Query<(&Name, &Team), With<Unit>>,
+    animation_query: Query<(), With<MovementAnimation>>,
+    playback: Option<Res<ReplayPlayback>>,
+    mut end_turn_event_w: EventWriter<EndTurnEvent>,
+) {
+    egui::Window::new("Turn")
+        .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-10., -10.))
+        .resizable(false)
+        .collapsible(false)
+        .show(contexts.ctx_mut(), |ui| {
+            match *turn_mode {
+                TurnMode::Teams => {
+                    ui.label(format!("Round {}", turn_manager.round));
+
+                    if let Some(team) = turn_manager.active_team() {
+                        ui.label(format!("Team {} to play", team));
+                    }
+                }
+                TurnMode::Initiative => {
+                    ui.label(format!("Round {}", turn_queue.round));
+
+                    if let Some(Ok((name, team))) =
+                        turn_queue.active_unit().map(|unit| unit_query.get(unit))
+                    {
+                        ui.label(format!("{} (team {}) to play", name, team.id));
+                    }
+                }
+            }
+
+            if ui
+                .add_enabled(
+                    animation_query.is_empty() && playback.is_none(),
+                    egui::Button::new("End turn"),
+                )
+                .clicked()
+            {
+                end_turn_event_w.send(EndTurnEvent);
+            }
+        });
+}
+
+fn end_turn(
+    mut end_turn_event_r: EventReader<EndTurnEvent>,
+    mut history: ResMut<CommandHistory>,
+    mut command_applied_event_w: EventWriter<CommandAppliedEvent>,
+    mut turn_started_event_w: EventWriter<TurnStartedEvent>,
+    mut turn_ended_event_w: EventWriter<TurnEndedEvent>,
+    mut turn_manager: ResMut<TurnManager>,
+    mut selected_unit: ResMut<CurrentActiveUnit>,
+    mut unit_query: Query<(&Team, &mut Mp), With<Unit>>,
+) {
+    for _ in end_turn_event_r.read() {
+        let Some(team) = turn_manager.active_team() else {
+            continue;
+        };
+        history.push(GameCommand::EndTurn);
+        command_applied_event_w.send(CommandAppliedEvent {
+            action: ReplayAction::EndTurn,
+        });
+        turn_ended_event_w.send(TurnEndedEvent { team });
+
+        turn_manager.active += 1;
+        if turn_manager.active == turn_manager.teams.len() {
+            turn_manager.active = 0;
+            turn_manager.round += 1;
+        }
+
+        let Some(next_team) = turn_manager.active_team() else {
+            continue;
+        };
+
+        for (_, mut mp) in unit_query
+            .iter_mut()
+            .filter(|(team, _)| team.id == next_team)
+        {
+            mp.current = mp.base;
+        }
+
+        selected_unit.entity = None;
+        turn_started_event_w.send(TurnStartedEvent {
+            team: next_team,
+            unit: None,
+        });
+    }
+}
+
+/// Orders units by decreasing initiative, entity order breaking ties so rounds are stable.
+fn initiative_order(mut units: Vec<(Entity, usize)>) -> VecDeque<Entity> {
+    units.sort_by(|(a, a_initiative), (b, b_initiative)| {
+        b_initiative.cmp(a_initiative).then(a.cmp(b))
+    });
+
+    units.into_iter().map(|(entity, _)| entity).collect()
+}
+
+/// Refills the MP of the unit at the front of the queue and hands it the controls.
+fn start_unit_turn(
+    turn_queue: &mut TurnQueue,
+    selected_unit: &mut CurrentActiveUnit,
+    unit_query: &mut Query<(Entity, &Team, &Initiative, &mut Mp), With<Unit>>,
+    turn_started_event_w: &mut EventWriter<TurnStartedEvent>,
+) {
+    turn_queue.active_team = None;
+
+    let Some(unit) = turn_queue.active_unit() else {
+        selected_unit.entity = None;
+        return;
+    };
+
+    if let Ok((_, team, _, mut mp)) = unit_query.get_mut(unit) {
+        mp.current = mp.base;
+        turn_queue.active_team = Some(team.id);
+        selected_unit.entity = Some(unit);
+        turn_started_event_w.send(TurnStartedEvent {
+            team: team.id,
+            unit: Some(unit),
+        });
+    }
+}
+
+fn new_round(
+    turn_queue: &mut TurnQueue,
+    unit_query: &Query<(Entity, &Team, &Initiative, &mut Mp), With<Unit>>,
+) {
+    turn_queue.round += 1;
+    turn_queue.queue = initiative_order(
+        unit_query
+            .iter()
+            .map(|(entity, _, initiative, _)| (entity, initiative.value))
+            .collect(),
+    );
+}
+
+fn init_turn_queue(
+    mut turn_queue: ResMut<TurnQueue>,
+    mut selected_unit: ResMut<CurrentActiveUnit>,
+    mut turn_started_event_w: EventWriter<TurnStartedEvent>,
+    mut unit_query: Query<(Entity, &Team, &Initiative, &mut Mp), With<Unit>>,
+) {
+    turn_queue.round = 0;
+    new_round(&mut turn_queue, &unit_query);
+    start_unit_turn(
+        &mut turn_queue,
+        &mut selected_unit,
+        &mut unit_query,
+        &mut turn_started_event_w,
+    );
+}
+
+/// Keeps the queue in sync with units spawning or dying mid-round.
+fn update_turn_queue(
+    mut turn_queue: ResMut<TurnQueue>,
+    mut selected_unit: ResMut<CurrentActiveUnit>,
+    mut turn_started_event_w: EventWriter<TurnStartedEvent>,
+    mut turn_ended_event_w: EventWriter<TurnEndedEvent>,
+    mut removed_units: RemovedComponents<Unit>,
+    added_unit_query: Query<Entity, (Added<Unit>, Without<Restored>)>,
+    mut unit_query: Query<(Entity, &Team, &Initiative, &mut Mp), With<Unit>>,
+) {
+    let active_unit = turn_queue.active_unit();
+
+    for entity in removed_units.read() {
+        turn_queue.queue.retain(|&unit| unit != entity);
+    }
+
+    // newcomers wait for their initiative slot among the units that have not played yet,
+    // restored units are already queued by the save or have played this round
+    for entity in added_unit_query.iter() {
+        if turn_queue.queue.contains(&entity) {
+            continue;
+        }
+        let Ok((_, _, initiative, _)) = unit_query.get(entity) else {
+            continue;
+        };
+
+        let slot = (1..turn_queue.queue.len())
+            .find(|&index| {
+                unit_query
+                    .get(turn_queue.queue[index])
+                    .is_ok_and(|(_, _, other, _)| other.value < initiative.value)
+            })
+            .unwrap_or(turn_queue.queue.len());
+        turn_queue.queue.insert(slot, entity);
+    }
+
+    if turn_queue.active_unit() != active_unit {
+        // the playing unit died, its turn is over
+        if let (Some(_), Some(team)) = (active_unit, turn_queue.active_team) {
+            turn_ended_event_w.send(TurnEndedEvent { team });
+        }
+
+        if turn_queue.queue.is_empty() {
+            new_round(&mut turn_queue, &unit_query);
+        }
+
+        start_unit_turn(
+            &mut turn_queue,
+            &mut selected_unit,
+            &mut unit_query,
+            &mut turn_started_event_w,
+        );
+    }
+}
+
+fn end_unit_turn(
+    mut end_turn_event_r: EventReader<EndTurnEvent>,
+    mut history: ResMut<CommandHistory>,
+    mut command_applied_event_w: EventWriter<CommandAppliedEvent>,
+    mut turn_queue: ResMut<TurnQueue>,
+    mut selected_unit: ResMut<CurrentActiveUnit>,
+    mut turn_started_event_w: EventWriter<TurnStartedEvent>,
+    mut turn_ended_event_w: EventWriter<TurnEndedEvent>,
+    mut unit_query: Query<(Entity, &Team, &Initiative, &mut Mp), With<Unit>>,
+) {
+    for _ in end_turn_event_r.read() {
+        let Some(unit) = turn_queue.queue.pop_front() else {
+            continue;
+        };
+        history.push(GameCommand::EndTurn);
+        command_applied_event_w.send(CommandAppliedEvent {
+            action: ReplayAction::EndTurn,
+        });
+
+        if let Ok((_, team, _, _)) = unit_query.get(unit) {
+            turn_ended_event_w.send(TurnEndedEvent { team: team.id });
+        }
+
+        if turn_queue.queue.is_empty() {
+            new_round(&mut turn_queue, &unit_query);
+        }
+
+        start_unit_turn(
+            &mut turn_queue,
+            &mut selected_unit,
+            &mut unit_query,
+            &mut turn_started_event_w,
+        );
+    }
+}
@@ -1,18 +1,18 @@
 use bevy::{
     prelude::*,
     sprite::{MaterialMesh2dBundle, Mesh2dHandle},
-    transform,
 };
 
-use rand::Rng;
-
 use crate::{
     asset_loader::GameAssets,
-    cursor::CursorGridClickEvent,
+    combat::{Attack, Defense, Health, Targeting},
+    cursor::{CursorCancelEvent, CursorGridClickEvent},
     hex_pos::HexPos,
+    map::{terrain_costs, CurrentMap, Hex, MapData, Terrain, UnitSpawn},
     movement::{MovementHex, MovementRange, Mp},
     obstacle::Obstacle,
-    schedule::InGameSet,
+    schedule::{GameState, InGameSet, UpdateSet},
+    turn::{Initiative, Team, TurnManager, TurnMode},
     Axial, Vec3Extra,
 };
 
@@ -29,44 +29,93 @@ pub struct UnitPlugin;
 impl Plugin for UnitPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<CurrentActiveUnit>()
-            .add_systems(PostStartup, spawn_units.in_set(InGameSet::InitEntities))
             .add_systems(
-                PostStartup,
+                OnEnter(GameState::InGame),
+                spawn_units.in_set(InGameSet::InitEntities),
+            )
+            .add_systems(
+                OnEnter(GameState::InGame),
                 init_movement_range.in_set(InGameSet::InitMovementRange),
             )
             .add_systems(
                 Update,
-                (unit_clicked, despawn_movement_range, spawn_movement_range).chain(),
+                (
+                    unit_clicked.run_if(resource_equals(Targeting::None)),
+                    deselect_unit,
+                )
+                    .run_if(resource_equals(TurnMode::Teams))
+                    .in_set(UpdateSet::UserInput),
             );
     }
 }
 
-fn spawn_units(mut commands: Commands, game_assets: Res<GameAssets>) {
-    let units = vec![Axial::new(0, 0), Axial::new(1, -1)];
+fn spawn_units(
+    mut commands: Commands,
+    game_assets: Option<Res<GameAssets>>,
+    current_map: Res<CurrentMap>,
+    maps: Res<Assets<MapData>>,
+) {
+    let Some(map_data) = maps.get(&current_map.handle) else {
+        return;
+    };
+
+    for unit in &map_data.units {
+        spawn_unit(&mut commands, game_assets.as_deref(), unit);
+    }
+}
+
+pub fn spawn_unit(
+    commands: &mut Commands,
+    game_assets: Option<&GameAssets>,
+    unit: &UnitSpawn,
+) -> Entity {
+    let pos = unit.pos;
+    let transform = Transform {
+        translation: pos.to_vec3_pixel().set_z(2.),
+        ..default()
+    };
+
+    let mut entity = commands.spawn((
+        HexPos { pos },
+        Unit,
+        Obstacle,
+        MovementRange::default(),
+        Mp::new(unit.mp),
+        Team { id: unit.team },
+        Initiative {
+            value: unit.initiative,
+        },
+        Health::new(unit.hp),
+        Attack::new(unit.damage, unit.range),
+        Defense {
+            value: unit.defense,
+        },
+        Name::new(unit.name.clone().unwrap_or_else(|| "Unit".to_string())),
+    ));
+
+    match game_assets {
+        Some(game_assets) => {
+            let material = match unit.team {
+                0 => game_assets.red.clone(),
+                1 => game_assets.blue.clone(),
+                _ => game_assets.black.clone(),
+            };
 
-    for pos in units {
-        commands.spawn((
-            MaterialMesh2dBundle {
+            entity.insert(MaterialMesh2dBundle {
                 mesh: Mesh2dHandle(game_assets.circle.clone()),
-                material: game_assets.red.clone(),
-                transform: Transform {
-                    translation: pos.to_vec3_pixel().set_z(2.),
-                    ..default()
-                },
+                material,
+                transform,
                 ..default()
-            },
-            HexPos { pos },
-            Unit,
-            Obstacle,
-            MovementRange::default(),
-            Mp::new(4),
-            Name::new("Unit"),
-        ));
+            })
+        }
+        None => entity.insert(TransformBundle::from_transform(transform)),
     }
+    .id()
 }
 
 fn init_movement_range(
     obstacle_query: Query<&HexPos, With<Obstacle>>,
+    terrain_query: Query<(&HexPos, &Terrain), With<Hex>>,
     mut movement_query: Query<(&HexPos, &Mp, &mut MovementRange)>,
 ) {
     for (hex_pos, mp, mut movement_range) in movement_query.iter_mut() {
@@ -74,76 +123,48 @@ fn init_movement_range(
             .iter()
             .map(|pos| pos.pos)
             .collect::<Vec<Axial>>();
+        let costs = terrain_costs(&terrain_query);
 
-        movement_range.hexes = hex_pos.pos.new_bfs(&obstacles, mp.current);
+        movement_range.hexes = hex_pos.pos.dijkstra(&obstacles, &costs, mp.current);
     }
 }
 
 fn unit_clicked(
     mut cursor_grid_click_event_r: EventReader<CursorGridClickEvent>,
-    unit_query: Query<(&HexPos, Entity), With<Unit>>,
+    unit_query: Query<(&HexPos, &Team, Entity), With<Unit>>,
+    movement_hex_query: Query<&HexPos, With<MovementHex>>,
+    turn_manager: Res<TurnManager>,
     mut selected_unit: ResMut<CurrentActiveUnit>,
 ) {
     for event in cursor_grid_click_event_r.read() {
-        let units = unit_query.iter().collect::<Vec<(&HexPos, Entity)>>();
+        let units = unit_query.iter().collect::<Vec<(&HexPos, &Team, Entity)>>();
         let unit = units
             .iter()
-            .find(|(pos, _)| pos.pos == event.pos)
-            .map(|(_, entity)| *entity);
+            .find(|(pos, team, _)| pos.pos == event.pos && turn_manager.is_active(team))
+            .map(|(_, _, entity)| *entity);
 
         match unit {
             Some(entity) => {
                 selected_unit.entity = Some(entity);
             }
-            None => {}
-        }
-    }
-}
-
-fn spawn_movement_range(
-    current_active_unit: Res<CurrentActiveUnit>,
+            None => {
+                let empty = units.iter().all(|(pos, _, _)| pos.pos != event.pos);
+                let in_range = movement_hex_query.iter().any(|pos| pos.pos == event.pos);
 
-    mut commands: Commands,
-    game_assets: Res<GameAssets>,
-    movement_range_query: Query<&MovementRange>,
-) {
-    if current_active_unit.is_changed() {
-        match current_active_unit.entity {
-            Some(entity) => {
-                if let Ok(movement_range) = movement_range_query.get(entity) {
-                    for hex in &movement_range.hexes {
-                        commands.spawn((
-                            MaterialMesh2dBundle {
-                                mesh: Mesh2dHandle(game_assets.hexagone.clone()),
-                                material: game_assets.movement_hex_color.clone(),
-                                transform: Transform {
-                                    translation: hex.hex.to_vec3_pixel().set_z(1.),
-                                    ..default()
-                                },
-                                ..default()
-                            },
-                            MovementHex {
-                                distance: hex.distance,
-                            },
-                            HexPos { pos: hex.hex },
-                            Name::new("MovementHex"),
-                        ));
-                    }
+                if empty && !in_range && selected_unit.entity.is_some() {
+                    selected_unit.entity = None;
                 }
             }
-            None => {}
         }
     }
 }
 
-fn despawn_movement_range(
-    current_active_unit: Res<CurrentActiveUnit>,
-    mut commands: Commands,
-    movement_hex_query: Query<Entity, With<MovementHex>>,
+/// Only in team turns, in initiative turns the active unit is always the selected one.
+fn deselect_unit(
+    mut cursor_cancel_event_r: EventReader<CursorCancelEvent>,
+    mut selected_unit: ResMut<CurrentActiveUnit>,
 ) {
-    if current_active_unit.is_changed() {
-        for entity in movement_hex_query.iter() {
-            commands.entity(entity).despawn();
-        }
+    if cursor_cancel_event_r.read().count() > 0 && selected_unit.entity.is_some() {
+        selected_unit.entity = None;
     }
 }